@@ -4,7 +4,6 @@ use bevy::pbr::ScreenSpaceAmbientOcclusion;
 use bevy::post_process::bloom::Bloom;
 use bevy::render::view::Hdr;
 use bevy::{prelude::*, window::WindowResolution};
-use std::time::Duration;
 
 use bevy_sprite3d::prelude::*;
 
@@ -13,14 +12,6 @@ use rand::{prelude::SliceRandom, Rng};
 #[derive(States, Hash, Clone, PartialEq, Eq, Debug, Default)] #[rustfmt::skip]
 enum GameState { #[default] Loading, Ready }
 
-// #[derive(AssetCollection, Resource)]
-// struct ImageAssets {
-//     #[asset(texture_atlas(tile_size_x = 16., tile_size_y = 16.,
-//             columns = 30, rows = 35, padding_x = 10., padding_y = 10.,
-//             offset_x = 5., offset_y = 5.))]
-//     #[asset(path = "dungeon/tileset_padded.png")]
-//     tileset: Handle<TextureAtlas>,
-// }
 #[derive(Resource, Default)]
 struct ImageAssets
 {
@@ -49,32 +40,35 @@ fn main()
                                mut assets:   ResMut<ImageAssets>,
                                mut layouts:  ResMut<Assets<TextureAtlasLayout>>| {
 
-            assets.image = asset_server.load("dungeon/tileset_padded.png");
+            assets.image = asset_server.load("dungeon/tileset.png");
 
             assets.layout = layouts.add(
-                TextureAtlasLayout::from_grid(
-                                        UVec2::new(16, 16),
-                                        30,
-                                        35,
-                                        Some(UVec2::new(10, 10)),
-                                        Some(UVec2::new(5, 5)))
+                TextureAtlasLayout::from_grid(UVec2::new(16, 16), 30, 35, None, None)
             );
         })
 
         // every frame check if assets are loaded. Once they are, we can proceed with setup.
         .add_systems(Update, (
                        |asset_server   : Res<AssetServer>,
-                        assets         : Res<ImageAssets>,
+                        mut assets     : ResMut<ImageAssets>,
+                        mut images     : ResMut<Assets<Image>>,
+                        mut layouts    : ResMut<Assets<TextureAtlasLayout>>,
                         mut next_state : ResMut<NextState<GameState>>| {
 
             if asset_server.get_load_state(assets.image.id()).is_some_and(|s| s.is_loaded()) {
+                // bleed each tile's edges into 5px of padding, so neighbouring
+                // tiles don't show at the seams.
+                let (image, layout) = pad_atlas(images.get(&assets.image).unwrap(),
+                                                layouts.get(&assets.layout).unwrap(),
+                                                5).unwrap();
+                assets.image = images.add(image);
+                assets.layout = layouts.add(layout);
                 next_state.set(GameState::Ready);
             }
         }).run_if(in_state(GameState::Loading)) )
         .add_systems( OnEnter(GameState::Ready), setup )
         .add_systems( OnEnter(GameState::Ready), spawn_sprites )
         .add_systems( Update, animate_camera.run_if(in_state(GameState::Ready)) )
-        .add_systems( Update, animate_sprites.run_if(in_state(GameState::Ready)) )
         .add_systems( Update, face_camera.run_if(in_state(GameState::Ready)) )
         .insert_resource(ImageAssets::default())
         .run();
@@ -84,14 +78,6 @@ fn main()
 #[derive(Component)]
 struct FaceCamera; // tag entity to make it always face the camera
 
-#[derive(Component)]
-struct Animation
-{
-    frames:  Vec<usize>, // indices of all the frames in the animation
-    current: usize,
-    timer:   Timer,
-}
-
 #[rustfmt::skip]
 fn setup(mut commands: Commands,
          mut meshes: ResMut<Assets<Mesh>>,
@@ -193,155 +179,40 @@ fn spawn_sprites(mut commands: Commands, images: Res<ImageAssets>)
         row.push((0, 0));
     }
 
-    // might be nice to add built-in support for sprite-merging for tilemaps...
-    // though since all the meshes and materials are already cached and reused,
-    // I wonder how much of a speedup that'd actually be. Food for thought.
-
-    for y in 0..map.len() {
-        for x in 0..map[y].len() {
-            let index = map[y][x].0 * 30 + map[y][x].1;
-            let x = x as f32 - map[y].len() as f32 / 2.0;
-            let y = y as f32 - map.len() as f32 / 2.0;
-            if index == 0 {
-                continue;
-            }
-
-            let atlas = TextureAtlas {
-                layout: images.layout.clone(),
-                index: index as usize,
-            };
-
-            commands.spawn((
-                Sprite3d {
-                    pixels_per_metre: 16.,
-                    double_sided: false,
-                    ..default()
-                },
-                Sprite {
-                    image: images.image.clone(),
-                    texture_atlas: Some(atlas),
-                    ..default()
-                },
-                Transform::from_xyz(x, 0.0, y)
-                    .with_rotation(Quat::from_rotation_x(-std::f32::consts::PI / 2.0))
-            ));
-        }
-    }
-
-    // --------------------------- add some walls -------------------------
+    // the floor and walls are built from the map: walls go wherever floor
+    // meets empty space, two tiles tall, with corner pieces where they end.
+    // Tiles are centred on whole coordinates to line up with the rest of the
+    // sprites, so shift the level back half a tile.
+    let column = |tile_x: usize| vec![tile_x + 5 * 30, tile_x + 4 * 30];
+    let mut walls = vec![column(1); 9];
+    walls.extend([2, 2, 3, 3, 4, 4, 7, 8].map(column)); // (7 and 8 are staircases)
 
-    // first horizontally, then vertically, scan along the map. If we find
-    // a point transitioning from (0,0) to something else, add a wall there.
+    let (w, h) = (map[0].len(), map.len());
+    commands.spawn((
+        Sprite3dLevel::new(images.image.clone(),
+                           images.layout.clone(),
+                           UVec2::new(w as u32, h as u32),
+                           Vec2::ONE,
+                           LevelTiles { wall:           walls,
+                                        wall_left_end:  Some(column(0)),
+                                        wall_right_end: Some(column(5)),
+                                        wall_height:    2,
+                                        ..default() })
+            .with_floor_tiles(|pos| {
+                let (tile_y, tile_x) = map[pos.y as usize][pos.x as usize];
+                let index = (tile_y * 30 + tile_x) as usize;
+                (index != 0).then_some(index)
+            }),
+        Transform::from_xyz(-(w as f32) / 2.0 - 0.5, 0.0, -(h as f32) / 2.0 - 0.5),
+    ));
 
     let mut rng = rand::thread_rng();
 
-    // quick closure to get a random wall tile, avoiding staircases right next
-    // to each other (since that can look weird)
-    let mut time_since_staircase = 0;
-    let mut wall_index = || {
-        if time_since_staircase > 3 && rng.gen_bool(0.075) {
-            time_since_staircase = 0;
-            if rng.gen_bool(0.5) { 7 } else { 8 }
-        } else {
-            time_since_staircase += 1;
-            if rng.gen_bool(0.6) { 1 } else { rng.gen_range(2..=4) }
-        }
-    };
-
-    for y in 1..(map.len() - 1) {
-        for x in 0..(map[y].len() - 1) {
-            if (map[y][x] != (0, 0)) ^ (map[y][x + 1] == (0, 0)) {
-                continue;
-            }
-            let dir = if map[y][x] == (0, 0) { 1.0 } else { -1.0 };
-
-            let mut tile_x = wall_index();
-
-            if map[y][x] == (0, 0) { // literal corner cases. hah.
-                if map[y + 1][x + 1] == (0, 0) { tile_x = 0; }
-                if map[y - 1][x + 1] == (0, 0) { tile_x = 5; }
-            } else {
-                if map[y - 1][x] == (0, 0) { tile_x = 0; }
-                if map[y + 1][x] == (0, 0) { tile_x = 5; }
-            }
-
-            let x = x as f32 - map[y].len() as f32 / 2.0;
-            let y = y as f32 - map.len() as f32 / 2.0;
-
-            for i in [0,1] { // add bottom and top piece
-                let atlas = TextureAtlas {
-                    layout: images.layout.clone(),
-                    index: (tile_x + (5 - i) * 30) as usize,
-                };
-
-                commands.spawn((
-                    Sprite3d {
-                        pixels_per_metre: 16.,
-                        double_sided: false,
-                        ..default()
-                    },
-                    Sprite {
-                        image: images.image.clone(),
-                        texture_atlas: Some(atlas),
-                        ..default()
-                    },
-                    Transform::from_xyz(x+0.5, i as f32 + 0.499, y)
-                        .with_rotation(Quat::from_rotation_y(dir * std::f32::consts::PI / 2.0))
-                ));
-            }
-        }
-    }
-
-    // same thing again, but for the vertical walls
-    for x in 1..(map[0].len() - 1) {
-        for y in 0..(map.len() - 1) {
-            if (map[y][x] != (0, 0)) ^ (map[y + 1][x] == (0, 0)) {
-                continue;
-            }
-            let dir = if map[y][x] == (0, 0) { 1.0 } else { -1.0 };
-
-            let mut tile_x = wall_index();
-
-            if map[y][x] == (0, 0) {
-                if map[y + 1][x - 1] == (0, 0) { tile_x = 0; }
-                if map[y + 1][x + 1] == (0, 0) { tile_x = 5; }
-            } else {
-                if map[y][x + 1] == (0, 0) { tile_x = 0; }
-                if map[y][x - 1] == (0, 0) { tile_x = 5; }
-            }
-
-            let x = x as f32 - map[y].len() as f32 / 2.0;
-            let y = y as f32 - map.len() as f32 / 2.0;
-
-            for i in [0, 1] { // add bottom and top piece
-                let atlas = TextureAtlas {
-                    layout: images.layout.clone(),
-                    index: (tile_x + (5 - i) * 30) as usize,
-                };
-
-                commands.spawn((
-                    Sprite3d {
-                        pixels_per_metre: 16.,
-                        double_sided: false,
-                        ..default()
-                    },
-                    Sprite {
-                        image: images.image.clone(),
-                        texture_atlas: Some(atlas),
-                        ..default()
-                    },
-                    Transform::from_xyz(x, i as f32 + 0.499, y + 0.5)
-                        .with_rotation(Quat::from_rotation_y((dir - 1.0) * std::f32::consts::PI / 2.0)),
-                ));
-            }
-        }
-    }
-
     // --------------------- characters, enemies, props ---------------------
 
     let mut entity = |(x, y), tile_x, tile_y, height, frames| {
-        let mut timer = Timer::from_seconds(0.4, TimerMode::Repeating);
-        timer.set_elapsed(Duration::from_secs_f32(rng.gen_range(0.0..0.4)));
+        // start each entity on a random frame so they don't all move in lockstep
+        let start = rng.gen_range(0..frames);
 
         for i in 0usize..height {
             let atlas = TextureAtlas {
@@ -364,11 +235,13 @@ fn spawn_sprites(mut commands: Commands, images: Res<ImageAssets>)
             ));
 
             if frames > 1 {
-                c.insert(Animation {
-                    frames: (0..frames).map(|j| j + tile_x + (tile_y - i) * 30_usize).collect(),
-                    current: 0,
-                    timer: timer.clone(),
-                });
+                let mut animation = Sprite3dAnimation::from_clip(Sprite3dClip::new(
+                    "idle",
+                    (0..frames).map(|j| j + tile_x + (tile_y - i) * 30_usize).collect(),
+                    0.4,
+                ));
+                animation.seek(start);
+                c.insert(animation);
             }
         }
     };
@@ -406,11 +279,9 @@ fn spawn_sprites(mut commands: Commands, images: Res<ImageAssets>)
             ..default()
         },
         Transform::from_xyz(2.0, 0.5, -5.5),
-        Animation {
-            frames: vec![30*32 + 14, 30*32 + 15, 30*32 + 16],
-            current: 0,
-            timer: Timer::from_seconds(0.2, TimerMode::Repeating),
-        },
+        Sprite3dAnimation::from_clip(
+            Sprite3dClip::new("burn", vec![30*32 + 14, 30*32 + 15, 30*32 + 16], 0.2)
+        ),
         FaceCamera {}
     ));
     commands.spawn((
@@ -478,20 +349,6 @@ fn animate_camera(time: Res<Time>, mut transform: Single<&mut Transform, With<Ca
 }
 
 
-fn animate_sprites(time: Res<Time>, mut query: Query<(&mut Animation, &mut Sprite)>)
-{
-    for (mut animation, mut sprite) in query.iter_mut() {
-        animation.timer.tick(time.delta());
-        if animation.timer.just_finished() {
-            let atlas = sprite.texture_atlas.as_mut().unwrap();
-            atlas.index = animation.frames[animation.current];
-            animation.current += 1;
-            animation.current %= animation.frames.len();
-        }
-    }
-}
-
-
 #[rustfmt::skip]
 fn face_camera(
     cam_transform: Single<&Transform, With<Camera>>,
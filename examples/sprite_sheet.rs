@@ -11,9 +11,6 @@ struct ImageAssets
     layout: Handle<TextureAtlasLayout>, // code in this file disappears if something like bevy_asset_loader is used.
 }
 
-#[derive(Component, Deref, DerefMut)]
-struct AnimationTimer(Timer);
-
 fn main()
 {
     App::new().add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()))
@@ -33,8 +30,6 @@ fn main()
               // run `setup` every frame while loading. Once it detects the right
               // conditions it'll switch to the next state.
               .add_systems(Update, setup.run_if(in_state(GameState::Loading)))
-              // every frame, animate the sprite
-              .add_systems(Update, animate_sprite.run_if(in_state(GameState::Ready)))
               .insert_resource(ImageAssets::default())
               .run();
 }
@@ -71,24 +66,10 @@ fn setup(
             // pivot: Some(Vec2::new(0.5, 0.5)),
             ..default()
         },
-        AnimationTimer(Timer::from_seconds(0.1, TimerMode::Repeating)),
+        // cycle through all 7 frames, 0.1s each
+        Sprite3dAnimation::from_clip(Sprite3dClip::new("run", (0..7).collect(), 0.1)),
     ));
 
     // -----------------------------------------------------------------------
 }
 
-
-fn animate_sprite(time: Res<Time>,
-                  atlases: Res<Assets<TextureAtlasLayout>>,
-                  mut query: Query<(&mut AnimationTimer, &mut Sprite)>)
-{
-    for (mut timer, mut sprite) in query.iter_mut() {
-        timer.tick(time.delta());
-        if timer.just_finished() {
-            let atlas = sprite.texture_atlas.as_mut().unwrap();
-            if let Some(layouts) = atlases.get(&atlas.layout) {
-                atlas.index = (atlas.index + 1) % layouts.textures.len();
-            }
-        }
-    }
-}
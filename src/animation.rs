@@ -0,0 +1,449 @@
+use bevy::prelude::*;
+
+use crate::DirectionalSprite3d;
+
+/// How a [`Sprite3dClip`] continues once it runs past its last frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PlaybackMode
+{
+    /// Wrap back around to the first frame.
+    #[default]
+    Loop,
+    /// Stop on the last frame.
+    Once,
+    /// Play forwards, then backwards, then forwards again, etc.
+    PingPong,
+    /// Loop, but play the frames last-to-first.
+    Reverse,
+}
+
+/// A named sequence of atlas indices, each shown for its own duration.
+#[derive(Clone, Debug)]
+pub struct Sprite3dClip
+{
+    pub name:      String,
+
+    /// Indices into the sprite's `TextureAtlasLayout`, in playback order.
+    pub frames:    Vec<usize>,
+
+    /// Time each frame is shown for, in seconds. If there are fewer durations
+    /// than frames, the last duration is reused for the remaining frames.
+    pub durations: Vec<f32>,
+
+    pub mode:      PlaybackMode,
+
+    /// Positions in `frames` that trigger a [`Sprite3dFrameEvent`] each time
+    /// they're reached, eg. the hit frame of an attack.
+    pub events:    Vec<usize>,
+}
+
+impl Sprite3dClip
+{
+    /// A looping clip where every frame is shown for `frame_duration` seconds.
+    pub fn new(name: impl Into<String>, frames: Vec<usize>, frame_duration: f32) -> Self
+    {
+        Self { name: name.into(),
+               frames,
+               durations: vec![frame_duration],
+               mode: PlaybackMode::Loop,
+               events: Vec::new() }
+    }
+
+    /// A looping clip built from `(atlas index, seconds)` pairs.
+    pub fn from_frames(name: impl Into<String>, frames: impl IntoIterator<Item = (usize, f32)>) -> Self
+    {
+        let (frames, durations) = frames.into_iter().unzip();
+        Self { name: name.into(), frames, durations, mode: PlaybackMode::Loop, events: Vec::new() }
+    }
+
+    pub fn with_mode(mut self, mode: PlaybackMode) -> Self
+    {
+        self.mode = mode;
+        self
+    }
+
+    /// Marks position `frame` in `frames` to trigger a [`Sprite3dFrameEvent`].
+    pub fn with_event(mut self, frame: usize) -> Self
+    {
+        self.events.push(frame);
+        self
+    }
+
+    /// How long frame `frame` (a position in `frames`, not an atlas index) is
+    /// shown for.
+    pub fn frame_duration(&self, frame: usize) -> f32
+    {
+        self.durations.get(frame).or(self.durations.last()).copied().unwrap_or(0.)
+    }
+
+    /// Total length of one pass through the clip, in seconds.
+    pub fn duration(&self) -> f32 { (0..self.frames.len()).map(|i| self.frame_duration(i)).sum() }
+}
+
+/// Triggered on a sprite when its animation reaches a frame listed in
+/// [`Sprite3dClip::events`].
+#[derive(EntityEvent, Clone, Debug)]
+pub struct Sprite3dFrameEvent
+{
+    pub entity: Entity,
+    pub clip:   String,
+    /// The position within the clip, not the atlas index.
+    pub frame:  usize,
+}
+
+/// Triggered on a sprite each time a looping, reversed or ping-pong clip
+/// completes a full cycle.
+#[derive(EntityEvent, Clone, Debug)]
+pub struct Sprite3dClipLooped
+{
+    pub entity: Entity,
+    pub clip:   String,
+    pub frame:  usize,
+}
+
+/// Triggered on a sprite when a `PlaybackMode::Once` clip reaches its end.
+#[derive(EntityEvent, Clone, Debug)]
+pub struct Sprite3dClipFinished
+{
+    pub entity: Entity,
+    pub clip:   String,
+    pub frame:  usize,
+}
+
+// Things that happened to an animation during a tick, to be turned into events.
+pub(crate) enum Happening
+{
+    Frame(usize),
+    Looped(usize),
+    Finished(usize),
+}
+
+/// Plays [`Sprite3dClip`]s on a `Sprite3d` by driving `Sprite::texture_atlas`'s
+/// index. Since meshes for every atlas index are built up-front, changing
+/// frames only swaps the entity's mesh handle.
+#[derive(Component, Clone, Debug)]
+pub struct Sprite3dAnimation
+{
+    pub clips:  Vec<Sprite3dClip>,
+
+    /// Playback rate multiplier. `1.0` (default) plays clips at their authored
+    /// speed.
+    pub speed:  f32,
+
+    pub paused: bool,
+
+    clip:       usize,
+    frame:      usize,
+    elapsed:    f32,
+    backwards:  bool,
+    finished:   bool,
+    // set when a frame was jumped to directly, so its event fires next tick.
+    entered:    bool,
+}
+
+impl Default for Sprite3dAnimation
+{
+    fn default() -> Self
+    {
+        Self { clips:     Vec::new(),
+               speed:     1.,
+               paused:    false,
+               clip:      0,
+               frame:     0,
+               elapsed:   0.,
+               backwards: false,
+               finished:  false,
+               entered:   false, }
+    }
+}
+
+impl Sprite3dAnimation
+{
+    /// Creates an animation from a set of clips, playing the first.
+    pub fn new(clips: Vec<Sprite3dClip>) -> Self
+    {
+        let mut animation = Self { clips, ..default() };
+        animation.restart();
+        animation
+    }
+
+    /// Creates an animation with a single clip.
+    pub fn from_clip(clip: Sprite3dClip) -> Self { Self::new(vec![clip]) }
+
+    pub fn with_speed(mut self, speed: f32) -> Self
+    {
+        self.speed = speed;
+        self
+    }
+
+    /// Switches to the clip called `name`, starting it from the beginning. Does
+    /// nothing if that clip is already playing. Returns `false` if no clip has
+    /// that name.
+    pub fn play(&mut self, name: &str) -> bool
+    {
+        let Some(clip) = self.clips.iter().position(|c| c.name == name) else {
+            return false;
+        };
+        if clip != self.clip || self.finished {
+            self.clip = clip;
+            self.restart();
+        }
+        true
+    }
+
+    /// Starts the current clip over from its first frame.
+    pub fn restart(&mut self)
+    {
+        self.elapsed = 0.;
+        self.backwards = false;
+        self.finished = false;
+        self.entered = true;
+        self.frame = match self.current_clip() {
+            Some(clip) if clip.mode == PlaybackMode::Reverse => clip.frames.len().saturating_sub(1),
+            _ => 0,
+        };
+    }
+
+    pub fn pause(&mut self) { self.paused = true; }
+
+    pub fn resume(&mut self) { self.paused = false; }
+
+    /// Jumps to a position within the current clip (clamped to its length).
+    pub fn seek(&mut self, frame: usize)
+    {
+        let len = self.current_clip().map_or(0, |c| c.frames.len());
+        self.frame = frame.min(len.saturating_sub(1));
+        self.elapsed = 0.;
+        self.finished = false;
+        self.entered = true;
+    }
+
+    pub fn current_clip(&self) -> Option<&Sprite3dClip> { self.clips.get(self.clip) }
+
+    /// The current position within the playing clip.
+    pub fn frame(&self) -> usize { self.frame }
+
+    /// The atlas index of the frame currently being shown.
+    pub fn atlas_index(&self) -> Option<usize>
+    {
+        self.current_clip().and_then(|c| c.frames.get(self.frame)).copied()
+    }
+
+    /// Whether a `PlaybackMode::Once` clip has reached its end.
+    pub fn is_finished(&self) -> bool { self.finished }
+
+    // advance the animation by `dt` seconds of (unscaled) time, recording
+    // anything worth triggering an event for.
+    fn tick(&mut self, dt: f32, happened: &mut Vec<Happening>)
+    {
+        if self.entered {
+            self.entered = false;
+            self.enter_frame(happened);
+        }
+        if self.paused || self.finished {
+            return;
+        }
+        self.elapsed += dt * self.speed.max(0.);
+
+        loop {
+            let Some(clip) = self.clips.get(self.clip) else { return };
+            let duration = clip.frame_duration(self.frame);
+            // a zero-length frame would spin forever.
+            if duration <= 0. || self.elapsed < duration {
+                return;
+            }
+            self.elapsed -= duration;
+            self.step(happened);
+            if self.finished {
+                self.elapsed = 0.;
+                return;
+            }
+        }
+    }
+
+    fn enter_frame(&self, happened: &mut Vec<Happening>)
+    {
+        if self.current_clip().is_some_and(|c| c.events.contains(&self.frame)) {
+            happened.push(Happening::Frame(self.frame));
+        }
+    }
+
+    // move to the next frame according to the clip's playback mode.
+    fn step(&mut self, happened: &mut Vec<Happening>)
+    {
+        let Some(clip) = self.clips.get(self.clip) else { return };
+        let len = clip.frames.len();
+        if len == 0 {
+            return;
+        }
+
+        match clip.mode {
+            PlaybackMode::Loop => {
+                self.frame = (self.frame + 1) % len;
+                if self.frame == 0 {
+                    happened.push(Happening::Looped(self.frame));
+                }
+            }
+            PlaybackMode::Reverse => {
+                self.frame = (self.frame + len - 1) % len;
+                if self.frame == len - 1 {
+                    happened.push(Happening::Looped(self.frame));
+                }
+            }
+            PlaybackMode::Once => {
+                if self.frame + 1 < len {
+                    self.frame += 1;
+                } else {
+                    self.finished = true;
+                    happened.push(Happening::Finished(self.frame));
+                    return;
+                }
+            }
+            PlaybackMode::PingPong => {
+                if len == 1 {
+                    return;
+                }
+                if self.backwards && self.frame == 0 {
+                    self.backwards = false;
+                } else if !self.backwards && self.frame + 1 == len {
+                    self.backwards = true;
+                }
+                if self.backwards { self.frame -= 1 } else { self.frame += 1 }
+                // back where we started: one full there-and-back cycle.
+                if self.backwards && self.frame == 0 {
+                    happened.push(Happening::Looped(self.frame));
+                }
+            }
+        }
+        self.enter_frame(happened);
+    }
+}
+
+// Advance every animation, and point its sprite at the current frame. Runs
+// before the mesh-swapping systems so a new frame shows up the same tick.
+pub(crate) fn animate_sprites(mut commands: Commands,
+                              time: Res<Time>,
+                              mut query: Query<(Entity,
+                                                &mut Sprite3dAnimation,
+                                                &mut Sprite,
+                                                Has<DirectionalSprite3d>)>,
+                              mut happened: Local<Vec<Happening>>)
+{
+    for (entity, mut animation, mut sprite, directional) in query.iter_mut() {
+        animation.tick(time.delta_secs(), &mut happened);
+
+        if !happened.is_empty() {
+            let clip = animation.current_clip().map(|c| c.name.clone()).unwrap_or_default();
+            for happening in happened.drain(..) {
+                let clip = clip.clone();
+                match happening {
+                    Happening::Frame(frame) => {
+                        commands.trigger(Sprite3dFrameEvent { entity, clip, frame })
+                    }
+                    Happening::Looped(frame) => {
+                        commands.trigger(Sprite3dClipLooped { entity, clip, frame })
+                    }
+                    Happening::Finished(frame) => {
+                        commands.trigger(Sprite3dClipFinished { entity, clip, frame })
+                    }
+                }
+            }
+        }
+
+        // directional sprites offset the frame themselves.
+        if directional {
+            continue;
+        }
+        let Some(index) = animation.atlas_index() else { continue };
+        // only touch the sprite when the frame actually changed, so we don't
+        // trip `Changed<Sprite>` every tick.
+        if sprite.texture_atlas.as_ref().is_some_and(|atlas| atlas.index != index) {
+            sprite.texture_atlas.as_mut().unwrap().index = index;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // the atlas index shown after each of `steps` one-frame ticks.
+    fn play(animation: &mut Sprite3dAnimation, steps: usize) -> Vec<usize>
+    {
+        let mut happened = Vec::new();
+        (0..steps).map(|_| {
+            animation.tick(1., &mut happened);
+            animation.atlas_index().unwrap()
+        }).collect()
+    }
+
+    fn clip(mode: PlaybackMode) -> Sprite3dClip { Sprite3dClip::new("clip", vec![10, 11, 12], 1.).with_mode(mode) }
+
+    #[test]
+    fn loop_wraps()
+    {
+        let mut animation = Sprite3dAnimation::from_clip(clip(PlaybackMode::Loop));
+        assert_eq!(animation.atlas_index(), Some(10));
+        assert_eq!(play(&mut animation, 5), [11, 12, 10, 11, 12]);
+    }
+
+    #[test]
+    fn once_stops_on_last_frame()
+    {
+        let mut animation = Sprite3dAnimation::from_clip(clip(PlaybackMode::Once));
+        assert_eq!(play(&mut animation, 4), [11, 12, 12, 12]);
+        assert!(animation.is_finished());
+    }
+
+    #[test]
+    fn ping_pong_bounces()
+    {
+        let mut animation = Sprite3dAnimation::from_clip(clip(PlaybackMode::PingPong));
+        assert_eq!(play(&mut animation, 6), [11, 12, 11, 10, 11, 12]);
+    }
+
+    #[test]
+    fn reverse_plays_backwards()
+    {
+        let mut animation = Sprite3dAnimation::from_clip(clip(PlaybackMode::Reverse));
+        assert_eq!(animation.atlas_index(), Some(12));
+        assert_eq!(play(&mut animation, 4), [11, 10, 12, 11]);
+    }
+
+    #[test]
+    fn seek_clamps_and_restarts_the_frame()
+    {
+        let mut animation = Sprite3dAnimation::from_clip(clip(PlaybackMode::Loop));
+        animation.tick(0.5, &mut Vec::new());
+        animation.seek(7);
+        assert_eq!(animation.frame(), 2);
+        // the half second already played doesn't carry over.
+        animation.tick(0.75, &mut Vec::new());
+        assert_eq!(animation.atlas_index(), Some(12));
+    }
+
+    #[test]
+    fn speed_scales_time()
+    {
+        let mut animation = Sprite3dAnimation::from_clip(clip(PlaybackMode::Loop)).with_speed(2.);
+        assert_eq!(play(&mut animation, 2), [12, 11]);
+
+        let mut animation = Sprite3dAnimation::from_clip(clip(PlaybackMode::Loop)).with_speed(0.5);
+        assert_eq!(play(&mut animation, 2), [10, 11]);
+    }
+
+    #[test]
+    fn loops_and_finishes_are_reported()
+    {
+        let mut happened = Vec::new();
+        let mut animation = Sprite3dAnimation::from_clip(clip(PlaybackMode::Loop));
+        animation.tick(3., &mut happened);
+        assert!(matches!(happened[..], [Happening::Looped(0)]));
+
+        happened.clear();
+        let mut animation = Sprite3dAnimation::from_clip(clip(PlaybackMode::Once));
+        animation.tick(3., &mut happened);
+        assert!(matches!(happened[..], [Happening::Finished(2)]));
+    }
+}
@@ -0,0 +1,102 @@
+use bevy::asset::{io::Reader, AssetLoader, LoadContext};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::sheet::{JsonSheet, JsonSlice};
+use crate::{PlaybackMode, Sprite3dAtlas, Sprite3dAtlasLoaderError, Sprite3dClip};
+
+/// Loads sprite sheets exported from Aseprite (`File > Export Sprite Sheet`,
+/// with "JSON Data" enabled, in either the hash or array flavour) as a
+/// [`Sprite3dAtlas`].
+///
+/// - each frame becomes an atlas index, in export order.
+/// - each tag becomes a [`Sprite3dClip`] with the frames' durations. Without
+///   any tags, a single looping clip called `"default"` covers every frame.
+/// - slice pivots become per-frame pivots in [`Sprite3dAtlas::frames`].
+///
+/// Registered for the `.aseprite.json` extension, so plain `.json` exports
+/// need renaming (or a `.meta` file naming this loader). The binary
+/// `.aseprite` format isn't supported -- export a sheet instead.
+#[derive(Default, TypePath)]
+pub struct AsepriteLoader;
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct AsepriteLoaderSettings
+{
+    /// The slice to take pivots from. `None` (default) uses the first slice
+    /// with a pivot set.
+    pub pivot_slice: Option<String>,
+}
+
+impl AssetLoader for AsepriteLoader
+{
+    type Asset = Sprite3dAtlas;
+    type Settings = AsepriteLoaderSettings;
+    type Error = Sprite3dAtlasLoaderError;
+
+    async fn load(&self,
+                  reader: &mut dyn Reader,
+                  settings: &AsepriteLoaderSettings,
+                  load_context: &mut LoadContext<'_>)
+                  -> Result<Sprite3dAtlas, Sprite3dAtlasLoaderError>
+    {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let sheet = JsonSheet::parse(&bytes)?;
+
+        let image_path = load_context.path().resolve_embed(&sheet.meta.image)?;
+        let image = load_context.load(image_path);
+        let layout = load_context.add_labeled_asset("layout".into(), sheet.layout());
+
+        let clips = if sheet.meta.frame_tags.is_empty() {
+            vec![Sprite3dClip::from_frames("default",
+                                           (0..sheet.frames.len()).map(|i| (i, sheet.duration(i))))]
+        } else {
+            sheet.meta.frame_tags.iter().map(|tag| {
+                // stale exports can name frames the sheet no longer has.
+                if tag.from > tag.to || tag.to >= sheet.frames.len() {
+                    let (name, from, to, count) = (&tag.name, tag.from, tag.to, sheet.frames.len());
+                    return Err(Sprite3dAtlasLoaderError::Invalid(format!("tag \"{name}\" covers frames {from}..={to} of {count}")));
+                }
+                // reversed tags keep their frames in order, `PlaybackMode::Reverse`
+                // takes care of playing them backwards.
+                let mode = match tag.direction.as_str() {
+                    "reverse" => PlaybackMode::Reverse,
+                    "pingpong" | "pingpong_reverse" => PlaybackMode::PingPong,
+                    _ if tag.repeat.as_deref() == Some("1") => PlaybackMode::Once,
+                    _ => PlaybackMode::Loop,
+                };
+                Ok(Sprite3dClip::from_frames(tag.name.clone(),
+                                             (tag.from..=tag.to).map(|i| (i, sheet.duration(i))))
+                       .with_mode(mode))
+            }).collect::<Result<_, _>>()?
+        };
+
+        let slice = match &settings.pivot_slice {
+            Some(name) => sheet.meta.slices.iter().find(|s| &s.name == name),
+            None => sheet.meta.slices.iter().find(|s| s.keys.iter().any(|k| k.pivot.is_some())),
+        };
+        let mut frames = sheet.frames();
+        frames.pivots = (0..sheet.frames.len())
+            .map(|i| slice.and_then(|s| slice_pivot(s, i, sheet.frames[i].source_size())))
+            .collect();
+        let frames = load_context.add_labeled_asset("frames".into(), frames);
+
+        Ok(Sprite3dAtlas { image, layout, frames, clips })
+    }
+
+    fn extensions(&self) -> &[&str] { &["aseprite.json"] }
+}
+
+// The pivot of `slice` on `frame`, converted from Aseprite's pixel coordinates
+// (top-left origin, relative to the slice bounds) to Sprite3d's (bottom-left
+// origin, as a fraction of the frame size).
+fn slice_pivot(slice: &JsonSlice, frame: usize, frame_size: UVec2) -> Option<Vec2>
+{
+    // each key applies from its frame onwards, until the next key.
+    let key = slice.keys.iter().filter(|k| k.frame <= frame).max_by_key(|k| k.frame)?;
+    let pivot = key.pivot?;
+    let x = key.bounds.x as f32 + pivot.x;
+    let y = key.bounds.y as f32 + pivot.y;
+    Some(Vec2::new(x / frame_size.x as f32, 1. - y / frame_size.y as f32))
+}
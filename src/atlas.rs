@@ -0,0 +1,84 @@
+use bevy::prelude::*;
+
+use crate::{Sprite3d, Sprite3dAnimation, Sprite3dClip};
+
+/// A sprite sheet loaded from an external tool: the image, its atlas layout,
+/// and any animation clips and pivots the tool exported alongside it.
+///
+/// Wait for it to be loaded *with dependencies* (the image is loaded
+/// separately) before spawning sprites from it.
+#[derive(Asset, TypePath, Debug)]
+pub struct Sprite3dAtlas
+{
+    #[dependency]
+    pub image:  Handle<Image>,
+
+    #[dependency]
+    pub layout: Handle<TextureAtlasLayout>,
+
+    /// Pivots and trimming info for each frame. Pass this to `Sprite3d::frames`.
+    #[dependency]
+    pub frames: Handle<Sprite3dFrames>,
+
+    pub clips:  Vec<Sprite3dClip>,
+}
+
+impl Sprite3dAtlas
+{
+    /// A `Sprite` showing atlas index `index` of this sheet.
+    pub fn sprite(&self, index: usize) -> Sprite
+    {
+        Sprite { image: self.image.clone(),
+                 texture_atlas: Some(TextureAtlas { layout: self.layout.clone(), index }),
+                 ..default() }
+    }
+
+    /// A `Sprite3d` using this sheet's frame data, with everything else default.
+    pub fn sprite3d(&self, pixels_per_metre: f32) -> Sprite3d
+    {
+        Sprite3d { pixels_per_metre, frames: Some(self.frames.clone()), ..default() }
+    }
+
+    /// An animation containing all of this sheet's clips, playing the first.
+    pub fn animation(&self) -> Sprite3dAnimation { Sprite3dAnimation::new(self.clips.clone()) }
+
+    /// The clip called `name`, if the sheet has one.
+    pub fn clip(&self, name: &str) -> Option<&Sprite3dClip> { self.clips.iter().find(|c| c.name == name) }
+}
+
+
+/// Where a trimmed atlas frame sat within the original, untrimmed image, in
+/// pixels. Packers cut transparent borders off of frames to save space, which
+/// would otherwise shift the sprite around as it animates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameTrim
+{
+    /// The top-left corner of the trimmed rect within the untrimmed image.
+    pub offset:      UVec2,
+    /// The size of the untrimmed image.
+    pub source_size: UVec2,
+}
+
+/// Per-frame data for a `TextureAtlasLayout`, indexed by atlas index. Every
+/// frame gets its own cached mesh, so frames can have different pivots (eg.
+/// to keep feet planted while a sprite animates) at no extra cost.
+#[derive(Asset, TypePath, Clone, Default, Debug)]
+pub struct Sprite3dFrames
+{
+    /// Each frame's pivot, in the same `(0,0)` to `(1,1)` space as
+    /// `Sprite3d::pivot`, relative to the untrimmed frame. `None` (or a
+    /// missing entry) falls back to `Sprite3d::pivot`.
+    pub pivots: Vec<Option<Vec2>>,
+
+    pub trims:  Vec<Option<FrameTrim>>,
+}
+
+impl Sprite3dFrames
+{
+    /// Frame data with only pivots set.
+    pub fn from_pivots(pivots: Vec<Option<Vec2>>) -> Self { Self { pivots, trims: Vec::new() } }
+
+    pub fn pivot(&self, index: usize) -> Option<Vec2> { self.pivots.get(index).copied().flatten() }
+
+    pub fn trim(&self, index: usize) -> Option<FrameTrim> { self.trims.get(index).copied().flatten() }
+}
@@ -0,0 +1,371 @@
+use bevy::platform::collections::{HashMap, HashSet};
+use bevy::prelude::*;
+
+use crate::level::cell_hash;
+use crate::Sprite3dTilemap;
+
+// neighbour bits, see `AutotileRule`.
+const NW: u8 = 1;
+const N: u8 = 2;
+const NE: u8 = 4;
+const W: u8 = 8;
+const E: u8 = 16;
+const SW: u8 = 32;
+const S: u8 = 64;
+const SE: u8 = 128;
+
+const NEIGHBOURS: [(IVec2, u8); 8] = [(IVec2::new(-1, -1), NW),
+                                      (IVec2::new(0, -1), N),
+                                      (IVec2::new(1, -1), NE),
+                                      (IVec2::new(-1, 0), W),
+                                      (IVec2::new(1, 0), E),
+                                      (IVec2::new(-1, 1), SW),
+                                      (IVec2::new(0, 1), S),
+                                      (IVec2::new(1, 1), SE)];
+
+/// Picks a tile for cells whose neighbours match a pattern.
+///
+/// Neighbours with the same terrain set their bit (`AutotileRule::N`,
+/// `AutotileRule::NE`, ...) in the cell's mask. The rule matches when
+/// `mask & care == bits`.
+#[derive(Clone, Debug)]
+pub struct AutotileRule
+{
+    pub bits:     u8,
+
+    /// Which neighbours the rule looks at.
+    pub care:     u8,
+
+    /// `(atlas index, weight)` pairs to pick between. Heavier variants are
+    /// picked more often.
+    pub variants: Vec<(usize, f32)>,
+}
+
+impl AutotileRule
+{
+    pub const NW: u8 = NW;
+    pub const N: u8 = N;
+    pub const NE: u8 = NE;
+    pub const W: u8 = W;
+    pub const E: u8 = E;
+    pub const SW: u8 = SW;
+    pub const S: u8 = S;
+    pub const SE: u8 = SE;
+
+    /// A rule that always places `index` when the neighbours under `care`
+    /// are exactly `bits`.
+    pub fn new(bits: u8, care: u8, index: usize) -> Self { Self { bits, care, variants: vec![(index, 1.)] } }
+
+    pub fn with_variant(mut self, index: usize, weight: f32) -> Self
+    {
+        self.variants.push((index, weight));
+        self
+    }
+
+    fn matches(&self, mask: u8) -> bool { mask & self.care == self.bits }
+
+    // a weighted choice between variants, from a hash of the cell.
+    fn pick(&self, hash: u64) -> Option<usize>
+    {
+        let total: f32 = self.variants.iter().map(|(_, w)| w.max(0.)).sum();
+        if total <= 0. {
+            return self.variants.first().map(|(i, _)| *i);
+        }
+        let mut r = (hash >> 40) as f32 / (1u64 << 24) as f32 * total;
+        for &(index, weight) in &self.variants {
+            r -= weight.max(0.);
+            if r < 0. {
+                return Some(index);
+            }
+        }
+        self.variants.last().map(|(i, _)| *i)
+    }
+}
+
+/// The rules for one terrain, checked in order until one matches.
+#[derive(Clone, Debug)]
+pub struct AutotileRules
+{
+    pub rules:             Vec<AutotileRule>,
+
+    /// Only count a corner neighbour if both edges next to it match too, as
+    /// blob tilesets expect. Reduces the 256 possible masks to 47.
+    pub normalise_corners: bool,
+
+    /// Whether the edge of the map counts as the same terrain. `true` by
+    /// default, so terrain runs off the map rather than being bordered.
+    pub edges_match:       bool,
+}
+
+impl Default for AutotileRules
+{
+    fn default() -> Self { Self { rules: Vec::new(), normalise_corners: false, edges_match: true } }
+}
+
+impl AutotileRules
+{
+    /// Custom rules, checked in order.
+    pub fn custom(rules: Vec<AutotileRule>) -> Self { Self { rules, ..default() } }
+
+    /// A 16 tile "Wang" or "marching squares" set, which only looks at the
+    /// four edges. `tiles` is indexed by `N=1 | E=2 | S=4 | W=8`.
+    pub fn wang16(tiles: [usize; 16]) -> Self
+    {
+        let rules = tiles.iter().enumerate().map(|(i, &index)| {
+            let bits = [(1, N), (2, E), (4, S), (8, W)].iter().filter(|(b, _)| i & b != 0).fold(0, |m, (_, bit)| m | bit);
+            AutotileRule::new(bits, N | E | S | W, index)
+        }).collect();
+        Self::custom(rules)
+    }
+
+    /// A 47 tile "blob" set. `tiles` is in order of increasing mask, with
+    /// the bits above and corners only set next to two set edges -- the
+    /// usual layout of blob tilesets: `0, 2, 8, 10, 11, 16, 18, 22, ...255`.
+    pub fn blob47(tiles: [usize; 47]) -> Self
+    {
+        let masks = (0..=255u8).filter(|&m| normalise(m) == m);
+        let rules = masks.zip(tiles).map(|(mask, index)| AutotileRule::new(mask, 0xff, index)).collect();
+        Self { normalise_corners: true, ..Self::custom(rules) }
+    }
+
+    /// Adds a weighted alternative to the first rule matching `mask`, eg.
+    /// cracked variants of a fully surrounded floor tile.
+    pub fn with_variant(mut self, mask: u8, index: usize, weight: f32) -> Self
+    {
+        let mask = if self.normalise_corners { normalise(mask) } else { mask };
+        if let Some(rule) = self.rules.iter_mut().find(|r| r.matches(mask)) {
+            rule.variants.push((index, weight));
+        }
+        self
+    }
+
+    pub fn with_edges_match(mut self, edges_match: bool) -> Self
+    {
+        self.edges_match = edges_match;
+        self
+    }
+
+    /// The atlas index for a cell with neighbour mask `mask`. `hash` picks
+    /// between variants.
+    pub fn resolve(&self, mask: u8, hash: u64) -> Option<usize>
+    {
+        let mask = if self.normalise_corners { normalise(mask) } else { mask };
+        self.rules.iter().find(|r| r.matches(mask)).and_then(|r| r.pick(hash))
+    }
+}
+
+// drop corner bits that don't have both their edges set.
+fn normalise(mask: u8) -> u8
+{
+    let mut mask = mask;
+    for (corner, a, b) in [(NW, N, W), (NE, N, E), (SW, S, W), (SE, S, E)] {
+        if mask & a == 0 || mask & b == 0 {
+            mask &= !corner;
+        }
+    }
+    mask
+}
+
+/// Picks the tiles of a [`Sprite3dTilemap`] on the same entity from a terrain
+/// per cell, rather than by hand.
+///
+/// Each terrain has its own [`AutotileRules`]; terrain `0` is empty. Changing
+/// a cell's terrain re-resolves it and its neighbours before the tilemap is
+/// next rebuilt.
+#[derive(Component, Clone, Debug)]
+pub struct Sprite3dAutotile
+{
+    pub rules: HashMap<u32, AutotileRules>,
+
+    /// Picks between weighted variants.
+    pub seed:  u64,
+
+    size:      UVec2,
+    terrain:   Vec<u32>,
+    dirty:     HashSet<UVec2>,
+}
+
+impl Sprite3dAutotile
+{
+    /// An empty grid of `size` cells, which should match the tilemap.
+    pub fn new(size: UVec2) -> Self
+    {
+        Self { rules: HashMap::default(),
+               seed: 0,
+               size,
+               terrain: vec![0; (size.x * size.y) as usize],
+               dirty: HashSet::default() }
+    }
+
+    pub fn with_rules(mut self, terrain: u32, rules: AutotileRules) -> Self
+    {
+        self.rules.insert(terrain, rules);
+        self.dirty.extend(self.cells());
+        self
+    }
+
+    /// Sets every cell's terrain.
+    pub fn with_terrain(mut self, mut terrain: impl FnMut(UVec2) -> u32) -> Self
+    {
+        for pos in self.cells().collect::<Vec<_>>() {
+            let i = self.index(pos.as_ivec2()).unwrap();
+            self.terrain[i] = terrain(pos);
+        }
+        self.dirty.extend(self.cells());
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self
+    {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the terrain at `pos`, returning what was there. Does nothing if
+    /// `pos` is outside the grid.
+    pub fn set_terrain(&mut self, pos: UVec2, terrain: u32) -> Option<u32>
+    {
+        let i = self.index(pos.as_ivec2())?;
+        let old = std::mem::replace(&mut self.terrain[i], terrain);
+        if old != terrain {
+            for (offset, _) in NEIGHBOURS.iter().chain([&(IVec2::ZERO, 0)]) {
+                let p = pos.as_ivec2() + *offset;
+                if self.index(p).is_some() {
+                    self.dirty.insert(p.as_uvec2());
+                }
+            }
+        }
+        Some(old)
+    }
+
+    /// The terrain at `pos`, or `0` outside the grid.
+    pub fn terrain(&self, pos: UVec2) -> u32 { self.index(pos.as_ivec2()).map_or(0, |i| self.terrain[i]) }
+
+    /// Which neighbours of `pos` have the same terrain, as a mask of
+    /// [`AutotileRule`] bits.
+    pub fn mask(&self, pos: UVec2) -> u8
+    {
+        let terrain = self.terrain(pos);
+        let edges_match = self.rules.get(&terrain).is_some_and(|r| r.edges_match);
+        NEIGHBOURS.iter().fold(0, |mask, &(offset, bit)| {
+            let same = match self.index(pos.as_ivec2() + offset) {
+                Some(i) => self.terrain[i] == terrain,
+                None => edges_match,
+            };
+            if same { mask | bit } else { mask }
+        })
+    }
+
+    fn index(&self, pos: IVec2) -> Option<usize>
+    {
+        (pos.cmpge(IVec2::ZERO).all() && pos.cmplt(self.size.as_ivec2()).all())
+            .then(|| (pos.y as u32 * self.size.x + pos.x as u32) as usize)
+    }
+
+    fn cells(&self) -> impl Iterator<Item = UVec2> + use<>
+    {
+        let size = self.size;
+        (0..size.y).flat_map(move |y| (0..size.x).map(move |x| UVec2::new(x, y)))
+    }
+
+    // the atlas index for the terrain at `pos`.
+    fn resolve(&self, pos: UVec2) -> Option<usize>
+    {
+        let terrain = self.terrain(pos);
+        if terrain == 0 {
+            return None;
+        }
+        let rules = self.rules.get(&terrain)?;
+        rules.resolve(self.mask(pos), cell_hash(self.seed, pos.as_ivec2(), terrain as u64))
+    }
+}
+
+// Write the tiles of cells whose terrain (or a neighbour's) changed into
+// their tilemap. The tilemap only rebuilds chunks whose tiles actually
+// changed.
+pub(crate) fn resolve_autotiles(mut query: Query<(&mut Sprite3dAutotile, &mut Sprite3dTilemap)>)
+{
+    for (mut autotile, mut map) in query.iter_mut() {
+        if autotile.dirty.is_empty() {
+            continue;
+        }
+        let dirty: Vec<UVec2> = autotile.dirty.drain().collect();
+        for pos in dirty {
+            match autotile.resolve(pos) {
+                Some(index) => map.set_tile(pos, index),
+                None => map.clear_tile(pos),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn single_cell_edges()
+    {
+        let autotile = Sprite3dAutotile::new(UVec2::ONE).with_rules(1, AutotileRules::default())
+                                                        .with_terrain(|_| 1);
+        assert_eq!(autotile.mask(UVec2::ZERO), 0xff);
+
+        let autotile = autotile.with_rules(1, AutotileRules::default().with_edges_match(false));
+        assert_eq!(autotile.mask(UVec2::ZERO), 0);
+    }
+
+    #[test]
+    fn blob47_masks()
+    {
+        let masks: Vec<u8> = (0..=255u8).filter(|&m| normalise(m) == m).collect();
+        assert_eq!(masks.len(), 47);
+        assert_eq!(masks[..8], [0, 2, 8, 10, 11, 16, 18, 22]);
+        assert_eq!(masks.last(), Some(&255));
+
+        let rules = AutotileRules::blob47(std::array::from_fn(|i| i));
+        for (i, &mask) in masks.iter().enumerate() {
+            assert_eq!(rules.resolve(mask, 0), Some(i));
+        }
+        // corners without both their edges are ignored.
+        assert_eq!(rules.resolve(NW | NE | SW | SE, 0), Some(0));
+        assert_eq!(rules.resolve(N | W | NE, 0), rules.resolve(N | W, 0));
+    }
+
+    #[test]
+    fn wang16_masks()
+    {
+        let rules = AutotileRules::wang16(std::array::from_fn(|i| i));
+        assert_eq!(rules.resolve(0, 0), Some(0));
+        assert_eq!(rules.resolve(N, 0), Some(1));
+        assert_eq!(rules.resolve(E, 0), Some(2));
+        assert_eq!(rules.resolve(S, 0), Some(4));
+        assert_eq!(rules.resolve(W, 0), Some(8));
+        assert_eq!(rules.resolve(N | S, 0), Some(5));
+        // only edges count.
+        assert_eq!(rules.resolve(0xff, 0), Some(15));
+        assert_eq!(rules.resolve(NE | SW, 0), Some(0));
+    }
+
+    #[test]
+    fn mask_of_neighbours()
+    {
+        // a plus shape: the centre sees its four edges, but no corners.
+        let autotile = Sprite3dAutotile::new(UVec2::splat(3)).with_rules(1, AutotileRules::default().with_edges_match(false))
+                                                             .with_terrain(|p| (p.x == 1 || p.y == 1) as u32);
+        assert_eq!(autotile.mask(UVec2::ONE), N | E | S | W);
+        assert_eq!(autotile.mask(UVec2::new(1, 0)), SW | S | SE);
+    }
+
+    #[test]
+    fn single_cell_resolves_by_edges()
+    {
+        let tiles = std::array::from_fn(|i| i);
+        let autotile = Sprite3dAutotile::new(UVec2::ONE).with_rules(1, AutotileRules::wang16(tiles))
+                                                        .with_terrain(|_| 1);
+        assert_eq!(autotile.resolve(UVec2::ZERO), Some(15));
+
+        let autotile = autotile.with_rules(1, AutotileRules::wang16(tiles).with_edges_match(false));
+        assert_eq!(autotile.resolve(UVec2::ZERO), Some(0));
+    }
+}
@@ -0,0 +1,128 @@
+use bevy::prelude::*;
+use std::f32::consts::TAU;
+
+use crate::Sprite3dAnimation;
+
+/// A Doom-style sprite that shows a different atlas frame depending on which
+/// side of it the camera is looking from.
+///
+/// Frames are expected to be laid out with one row per direction, `stride`
+/// atlas indices apart. Row `i` is the view from `i * 360° / directions`
+/// counter-clockwise (seen from above) around the sprite, starting directly in
+/// front of it. The frame within the row comes from the entity's
+/// [`Sprite3dAnimation`] if it has one (with clips authored for row 0), or
+/// `frame` otherwise.
+///
+/// Every atlas index already has a cached mesh, so turning costs nothing more
+/// than swapping the mesh handle. Note this overwrites the sprite's atlas
+/// index every frame, and `flip_x` when `mirror` is set.
+#[derive(Component, Clone, Debug)]
+pub struct DirectionalSprite3d
+{
+    /// The number of directions, eg. `8` or `16`.
+    pub directions: usize,
+
+    /// The distance between rows in the atlas, in atlas indices.
+    pub stride:     usize,
+
+    /// The atlas index (in row 0) to show when there's no animation.
+    pub frame:      usize,
+
+    /// The direction the entity is facing, in world space. Only the horizontal
+    /// part is used. Defaults to `-Z`.
+    pub facing:     Vec3,
+
+    /// Only rows `0..=directions/2` (front, around one side, to the back) are
+    /// in the atlas. The other side is drawn by flipping those horizontally.
+    pub mirror:     bool,
+
+    /// Rotate the sprite about the Y axis to always face the camera. `true`
+    /// (default), since `facing` is what says where the entity is looking.
+    pub billboard:  bool,
+
+    direction:      usize,
+}
+
+impl DirectionalSprite3d
+{
+    pub fn new(directions: usize, stride: usize) -> Self
+    {
+        Self { directions,
+               stride,
+               frame: 0,
+               facing: Vec3::NEG_Z,
+               mirror: false,
+               billboard: true,
+               direction: 0 }
+    }
+
+    pub fn with_mirror(mut self, mirror: bool) -> Self
+    {
+        self.mirror = mirror;
+        self
+    }
+
+    /// The direction currently being shown, in `0..directions`.
+    pub fn direction(&self) -> usize { self.direction }
+
+    // the atlas row and whether to flip it, for a given direction.
+    fn row(&self, direction: usize) -> (usize, bool)
+    {
+        if self.mirror && direction > self.directions / 2 {
+            (self.directions - direction, true)
+        } else {
+            (direction, false)
+        }
+    }
+}
+
+// Pick each directional sprite's frame based on where the camera is. Runs
+// after animations so it can offset their frame into the right row.
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_directional_sprites(cameras: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+                                         mut query: Query<(&mut DirectionalSprite3d,
+                                                           &mut Sprite,
+                                                           &mut Transform,
+                                                           &GlobalTransform,
+                                                           Option<&Sprite3dAnimation>)>)
+{
+    let Some((_, camera)) = cameras.iter().find(|(c, _)| c.is_active) else { return };
+    let camera = camera.translation();
+
+    for (mut directional, mut sprite, mut transform, global, animation) in query.iter_mut() {
+        if directional.directions == 0 {
+            continue;
+        }
+        let to_camera = (camera - global.translation()) * Vec3::new(1., 0., 1.);
+        let facing = directional.facing * Vec3::new(1., 0., 1.);
+
+        if directional.billboard && to_camera != Vec3::ZERO {
+            // the quad faces +z, so point -z away from the camera.
+            transform.look_to(-to_camera, Vec3::Y);
+        }
+
+        // counter-clockwise angle (seen from above) from facing to the camera.
+        let angle = if to_camera == Vec3::ZERO || facing == Vec3::ZERO {
+            0.
+        } else {
+            let (f, c) = (facing.normalize(), to_camera.normalize());
+            f.cross(c).y.atan2(f.dot(c))
+        };
+        let n = directional.directions;
+        let direction = ((angle.rem_euclid(TAU) / TAU * n as f32).round() as usize) % n;
+        if directional.direction != direction {
+            directional.direction = direction;
+        }
+
+        let (row, flip) = directional.row(direction);
+        let frame = animation.and_then(Sprite3dAnimation::atlas_index).unwrap_or(directional.frame);
+        let index = frame + row * directional.stride;
+
+        if sprite.texture_atlas.as_ref().is_some_and(|atlas| atlas.index != index) {
+            sprite.texture_atlas.as_mut().unwrap().index = index;
+        }
+        if directional.mirror && sprite.flip_x != flip {
+            sprite.flip_x = flip;
+        }
+    }
+}
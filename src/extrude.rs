@@ -0,0 +1,108 @@
+use bevy::prelude::*;
+
+use crate::level::QuadMesh;
+
+// pixels at least this opaque count as solid when building side faces.
+const SOLID_ALPHA: f32 = 0.5;
+
+// Build a slab `depth` thick from the pixels of `rect` in `image`: the sprite
+// on the front and back, and side faces wherever a solid pixel borders a
+// transparent one (or the edge of the rect). Each side face samples the
+// centres of the edge texels it runs along, so it takes their colour.
+//
+// `size` is the world-space size of the rect, and `pivot` is relative to it.
+// Returns `None` if the image's pixels can't be read on the CPU.
+pub(crate) fn extruded_mesh(image: &Image, rect: URect, size: Vec2, pivot: Vec2, depth: f32) -> Option<Mesh>
+{
+    let image_size = image.size().as_vec2();
+    let rect_size = rect.size();
+    let solid = solid_pixels(image, rect, SOLID_ALPHA)?;
+    let is_solid = |x: i32, y: i32| {
+        x >= 0 && y >= 0 && x < rect_size.x as i32 && y < rect_size.y as i32
+            && solid[(y as u32 * rect_size.x + x as u32) as usize]
+    };
+
+    // local position of pixel corner `p` within the rect (y down, like the
+    // image), and the UV of texel coordinate `t`.
+    let pixel = size / rect_size.as_vec2();
+    let origin = Vec2::new(-pivot.x * size.x, (1. - pivot.y) * size.y);
+    let to_local = |p: Vec2| origin + Vec2::new(p.x, -p.y) * pixel;
+    let to_uv = |t: Vec2| ((rect.min.as_vec2() + t) / image_size).to_array();
+
+    let half_depth = depth / 2.;
+    let mut mesh = QuadMesh::default();
+
+    // the front and back faces cover the whole rect, leaving the material to
+    // cut out the transparent pixels.
+    let centre = to_local(rect_size.as_vec2() / 2.).extend(0.);
+    let half_size = size / 2.;
+    let (min, max) = (to_uv(Vec2::ZERO), to_uv(rect_size.as_vec2()));
+    mesh.push(centre + Vec3::Z * half_depth, Vec3::Z, Vec3::Y, half_size,
+              [[min[0], max[1]], max, [max[0], min[1]], min]);
+    mesh.push(centre - Vec3::Z * half_depth, -Vec3::Z, Vec3::Y, half_size,
+              [max, [min[0], max[1]], min, [max[0], min[1]]]);
+
+    // top and bottom faces, along runs of edge pixels in each row.
+    for y in 0..rect_size.y as i32 {
+        for (dy, out, up) in [(-1, Vec3::Y, -Vec3::Z), (1, -Vec3::Y, Vec3::Z)] {
+            for (start, end) in runs(rect_size.x as i32, |x| is_solid(x, y) && !is_solid(x, y + dy)) {
+                let edge = if dy < 0 { y } else { y + 1 } as f32;
+                let a = to_local(Vec2::new(start as f32, edge));
+                let b = to_local(Vec2::new(end as f32, edge));
+                let v = (y as f32 + 0.5 + rect.min.y as f32) / image_size.y;
+                let (u0, u1) = (to_uv(Vec2::new(start as f32, 0.))[0], to_uv(Vec2::new(end as f32, 0.))[0]);
+                mesh.push(((a + b) / 2.).extend(0.), out, up, Vec2::new((b.x - a.x) / 2., half_depth),
+                          [[u0, v], [u1, v], [u1, v], [u0, v]]);
+            }
+        }
+    }
+
+    // left and right faces, along runs of edge pixels in each column. These
+    // run up the sprite, so their UVs go from the bottom of the run to the top.
+    for x in 0..rect_size.x as i32 {
+        for (dx, out, up) in [(-1, -Vec3::X, -Vec3::Z), (1, Vec3::X, Vec3::Z)] {
+            for (start, end) in runs(rect_size.y as i32, |y| is_solid(x, y) && !is_solid(x + dx, y)) {
+                let edge = if dx < 0 { x } else { x + 1 } as f32;
+                let a = to_local(Vec2::new(edge, start as f32));
+                let b = to_local(Vec2::new(edge, end as f32));
+                let u = (x as f32 + 0.5 + rect.min.x as f32) / image_size.x;
+                let (v0, v1) = (to_uv(Vec2::new(0., start as f32))[1], to_uv(Vec2::new(0., end as f32))[1]);
+                mesh.push(((a + b) / 2.).extend(0.), out, up, Vec2::new((a.y - b.y) / 2., half_depth),
+                          [[u, v1], [u, v0], [u, v0], [u, v1]]);
+            }
+        }
+    }
+
+    mesh.build()
+}
+
+// which pixels of `rect` are at least `min_alpha` opaque, row by row from the
+// top. `None` if the image's pixels can't be read on the CPU.
+pub(crate) fn solid_pixels(image: &Image, rect: URect, min_alpha: f32) -> Option<Vec<bool>>
+{
+    let mut solid = Vec::with_capacity((rect.width() * rect.height()) as usize);
+    for y in rect.min.y..rect.max.y {
+        for x in rect.min.x..rect.max.x {
+            solid.push(image.get_color_at(x, y).ok()?.alpha() >= min_alpha);
+        }
+    }
+    Some(solid)
+}
+
+// the `[start, end)` runs of `0..len` where `f` holds.
+fn runs(len: i32, f: impl Fn(i32) -> bool) -> Vec<(i32, i32)>
+{
+    let mut runs = Vec::new();
+    let mut start = None;
+    for i in 0..=len {
+        match (start, i < len && f(i)) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                runs.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    runs
+}
@@ -0,0 +1,162 @@
+use std::ops::Range;
+
+use bevy::asset::embedded_asset;
+use bevy::mesh::MeshTag;
+use bevy::pbr::{ExtendedMaterial, MaterialExtension, OpaqueRendererMethod};
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+use bevy::render::render_resource::{AsBindGroup, ShaderType};
+use bevy::shader::ShaderRef;
+
+use crate::{Sprite3d, Sprite3dBuilder, Sprite3dInstanced};
+
+const SHADER: &str = "embedded://bevy_sprite3d/flipbook.wgsl";
+
+/// The material of sprites with a [`Sprite3dFlipbook`]: the sprite's usual
+/// `StandardMaterial`, plus the flipbook's settings.
+pub type Sprite3dFlipbookMaterial = ExtendedMaterial<StandardMaterial, FlipbookExtension>;
+
+/// Plays a range of a [`Sprite3d`]'s atlas frames on a loop, entirely in its
+/// shader. Unlike [`Sprite3dAnimation`](crate::Sprite3dAnimation), nothing
+/// is updated per entity each frame, so thousands of torches, flags or
+/// waterfalls cost no CPU time.
+///
+/// The atlas must be a grid of equally sized frames, as from
+/// `TextureAtlasLayout::from_grid` (padding and offsets are fine). Frames
+/// advance by index along each row, then onto the next. The sprite's
+/// material is swapped for a [`Sprite3dFlipbookMaterial`] once it's built,
+/// after which changes to its `Sprite` (eg. colour or image) aren't picked up
+/// -- change the flipbook to rebuild it. Flipbook sprites are drawn in the
+/// forward pass only, without a depth prepass or shadows, since neither
+/// could see the frame change. The entity's `MeshTag` holds the phase.
+#[derive(Component, Clone, Debug)]
+pub struct Sprite3dFlipbook
+{
+    /// Atlas indices to play, in order.
+    pub frames: Range<usize>,
+    pub fps:    f32,
+
+    /// How far into the loop to start, from `0.` to `1.`. Give neighbouring
+    /// props different phases so they don't animate in lockstep.
+    pub phase:  f32,
+}
+
+impl Sprite3dFlipbook
+{
+    pub fn new(frames: Range<usize>, fps: f32) -> Self { Self { frames, fps, phase: 0. } }
+
+    pub fn with_phase(mut self, phase: f32) -> Self
+    {
+        self.phase = phase;
+        self
+    }
+}
+
+/// The flipbook half of a [`Sprite3dFlipbookMaterial`].
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+pub struct FlipbookExtension
+{
+    #[uniform(100)]
+    flipbook: FlipbookUniform,
+}
+
+#[derive(ShaderType, Reflect, Debug, Clone, Copy)]
+struct FlipbookUniform
+{
+    stride:  Vec2,
+    columns: u32,
+    first:   u32,
+    count:   u32,
+    fps:     f32,
+}
+
+impl MaterialExtension for FlipbookExtension
+{
+    fn fragment_shader() -> ShaderRef { SHADER.into() }
+
+    fn enable_prepass() -> bool { false }
+
+    fn enable_shadows() -> bool { false }
+}
+
+pub(crate) struct FlipbookPlugin;
+
+impl Plugin for FlipbookPlugin
+{
+    fn build(&self, app: &mut App)
+    {
+        embedded_asset!(app, "flipbook.wgsl");
+        app.add_plugins(MaterialPlugin::<Sprite3dFlipbookMaterial>::default());
+    }
+}
+
+// the standard material a flipbook's material was built from, to rebuild it
+// from if the flipbook changes.
+#[derive(Component)]
+pub(crate) struct FlipbookBase(Handle<StandardMaterial>);
+
+// Swap built flipbook sprites onto a flipbook material, showing the first
+// frame's mesh for the shader to move along from. Materials are shared
+// between sprites with the same base material and flipbook.
+#[allow(clippy::type_complexity)]
+pub(crate) fn apply_flipbooks(mut commands: Commands,
+                              mut cache: Local<HashMap<(AssetId<StandardMaterial>, [u32; 6]), Handle<Sprite3dFlipbookMaterial>>>,
+                              standard_materials: Res<Assets<StandardMaterial>>,
+                              mut materials: ResMut<Assets<Sprite3dFlipbookMaterial>>,
+                              layouts: Res<Assets<TextureAtlasLayout>>,
+                              mut query: Query<(Entity,
+                                                &Sprite3dFlipbook,
+                                                &mut Sprite,
+                                                Option<&MeshMaterial3d<StandardMaterial>>,
+                                                Option<&FlipbookBase>),
+                                               (With<Sprite3d>,
+                                                Without<Sprite3dBuilder>,
+                                                Without<Sprite3dInstanced>,
+                                                Or<(Changed<Sprite3dFlipbook>, With<MeshMaterial3d<StandardMaterial>>)>)>)
+{
+    for (entity, flipbook, mut sprite, standard, base) in query.iter_mut() {
+        let Some(base) = standard.map(|m| m.0.clone()).or_else(|| base.map(|b| b.0.clone())) else {
+            continue;
+        };
+        let Some(layout) = sprite.texture_atlas.as_ref().and_then(|a| layouts.get(&a.layout)) else {
+            continue;
+        };
+        let Some(material) = standard_materials.get(&base) else {
+            continue;
+        };
+        let textures = &layout.textures;
+        if textures.is_empty() {
+            continue;
+        }
+
+        // on a grid, the first row is every frame level with the first.
+        let columns = textures.iter().take_while(|r| r.min.y == textures[0].min.y).count();
+        let stride = Vec2::new(textures.get(1).filter(|_| columns > 1).map_or(0., |r| (r.min.x - textures[0].min.x) as f32),
+                               textures.get(columns).map_or(0., |r| (r.min.y - textures[0].min.y) as f32))
+                     / layout.size.as_vec2();
+        let uniform = FlipbookUniform { stride,
+                                        columns: columns as u32,
+                                        first:   flipbook.frames.start.min(textures.len() - 1) as u32,
+                                        count:   flipbook.frames.len().max(1) as u32,
+                                        fps:     flipbook.fps, };
+
+        let key = (base.id(),
+                   [stride.x.to_bits(), stride.y.to_bits(), uniform.columns, uniform.first, uniform.count, uniform.fps.to_bits()]);
+        let handle = cache.entry(key).or_insert_with(|| {
+            // the prepass is off, so deferred rendering can't be used.
+            let base = StandardMaterial { opaque_render_method: OpaqueRendererMethod::Forward, ..material.clone() };
+            materials.add(ExtendedMaterial { base, extension: FlipbookExtension { flipbook: uniform } })
+        }).clone();
+
+        // `handle_texture_atlases` swaps in the first frame's mesh.
+        let atlas = sprite.texture_atlas.as_mut().unwrap();
+        if atlas.index != uniform.first as usize {
+            atlas.index = uniform.first as usize;
+        }
+        commands.entity(entity)
+                .remove::<MeshMaterial3d<StandardMaterial>>()
+                .insert((MeshMaterial3d(handle),
+                         MeshTag((flipbook.phase.rem_euclid(1.) * 65536.) as u32),
+                         FlipbookBase(base)));
+    }
+}
@@ -0,0 +1,47 @@
+#import bevy_pbr::{
+    forward_io::{FragmentOutput, VertexOutput},
+    mesh_functions::get_tag,
+    mesh_view_bindings::globals,
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::{alpha_discard, apply_pbr_lighting, main_pass_post_lighting_processing},
+    pbr_types::STANDARD_MATERIAL_FLAGS_UNLIT_BIT,
+}
+
+struct Flipbook {
+    // the UV offset from one column of the atlas to the next, and one row to
+    // the next.
+    stride: vec2<f32>,
+    columns: u32,
+    first: u32,
+    count: u32,
+    fps: f32,
+}
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(100) var<uniform> flipbook: Flipbook;
+
+// the column and row of atlas frame `index`.
+fn cell(index: u32) -> vec2<f32> {
+    return vec2<f32>(f32(index % flipbook.columns), f32(index / flipbook.columns));
+}
+
+@fragment
+fn fragment(vertex: VertexOutput, @builtin(front_facing) is_front: bool) -> FragmentOutput {
+    // the mesh's UVs cover the first frame, so shift them along to the current
+    // one. The mesh tag holds the sprite's phase, in 65536ths of a loop.
+    let phase = f32(get_tag(vertex.instance_index)) / 65536.0;
+    let frame = u32(floor(globals.time * flipbook.fps + phase * f32(flipbook.count))) % flipbook.count;
+    var in = vertex;
+    in.uv += (cell(flipbook.first + frame) - cell(flipbook.first)) * flipbook.stride;
+
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+
+    var out: FragmentOutput;
+    if (pbr_input.material.flags & STANDARD_MATERIAL_FLAGS_UNLIT_BIT) == 0u {
+        out.color = apply_pbr_lighting(pbr_input);
+    } else {
+        out.color = pbr_input.material.base_color;
+    }
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+    return out;
+}
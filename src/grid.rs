@@ -0,0 +1,215 @@
+use bevy::prelude::*;
+
+/// Which way up the hexagons of a [`TileGrid::Hex`] are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HexOrientation
+{
+    /// A corner at the top, so the hexagons form rows.
+    #[default]
+    Pointy,
+    /// An edge at the top, so the hexagons form columns.
+    Flat,
+}
+
+/// How tile positions map to the cells of a [`TileGrid::Hex`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HexLayout
+{
+    /// Odd rows (pointy) or columns (flat) are pushed half a tile along, so a
+    /// rectangular map is roughly rectangular on screen.
+    #[default]
+    OddOffset,
+    /// As `OddOffset`, pushing the even rows or columns instead.
+    EvenOffset,
+    /// Axial `(q, r)` coordinates, where a rectangular map is a rhombus.
+    Axial,
+}
+
+/// The shape of the grid a tilemap is laid out on.
+///
+/// Positions are worked out from the tile size, which for hex and isometric
+/// grids is the size of the tile's image, eg. `(2, 1)` for a 2:1 isometric
+/// tile: hexagons overlap by a quarter of their height (or width, when flat),
+/// and isometric tiles by half in each direction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TileGrid
+{
+    #[default]
+    Square,
+    Hex(HexOrientation, HexLayout),
+    /// A diamond isometric grid. `+x` runs down and to the right, `+y` down
+    /// and to the left, with the top corner of tile `(0, 0)` at the origin.
+    Isometric,
+}
+
+impl TileGrid
+{
+    /// The centre of tile `pos` within the tilemap's plane, in `(right,
+    /// down)` coordinates.
+    pub fn tile_to_plane(self, pos: IVec2, tile_size: Vec2) -> Vec2
+    {
+        match self {
+            TileGrid::Square => (pos.as_vec2() + 0.5) * tile_size,
+            TileGrid::Hex(orientation, layout) => {
+                let a = to_axial(pos, orientation, layout).as_vec2();
+                let p = match orientation {
+                    HexOrientation::Pointy => Vec2::new(a.x + a.y / 2., a.y * 0.75),
+                    HexOrientation::Flat => Vec2::new(a.x * 0.75, a.y + a.x / 2.),
+                };
+                (p + 0.5) * tile_size
+            }
+            TileGrid::Isometric => {
+                let p = pos.as_vec2();
+                Vec2::new(p.x - p.y, p.x + p.y + 1.) * tile_size / 2.
+            }
+        }
+    }
+
+    /// The tile containing `p`, a point in the plane in `(right, down)`
+    /// coordinates.
+    pub fn plane_to_tile(self, p: Vec2, tile_size: Vec2) -> IVec2
+    {
+        match self {
+            TileGrid::Square => (p / tile_size).floor().as_ivec2(),
+            TileGrid::Hex(orientation, layout) => {
+                // undo `tile_to_plane` to get fractional axial coordinates.
+                let p = p / tile_size - 0.5;
+                let axial = match orientation {
+                    HexOrientation::Pointy => {
+                        let r = p.y / 0.75;
+                        Vec2::new(p.x - r / 2., r)
+                    }
+                    HexOrientation::Flat => {
+                        let q = p.x / 0.75;
+                        Vec2::new(q, p.y - q / 2.)
+                    }
+                };
+                from_axial(round_axial(axial), orientation, layout)
+            }
+            TileGrid::Isometric => {
+                let p = p / (tile_size / 2.);
+                let (x, y) = ((p.x + p.y) / 2., (p.y - p.x) / 2.);
+                Vec2::new(x, y).floor().as_ivec2()
+            }
+        }
+    }
+
+    /// How far down the screen tile `pos` is, in tiles. Tiles further down
+    /// overlap the ones above them, so should be drawn later.
+    pub fn sort_depth(self, pos: IVec2, tile_size: Vec2) -> f32
+    {
+        self.tile_to_plane(pos, tile_size).y / tile_size.y
+    }
+}
+
+fn to_axial(pos: IVec2, orientation: HexOrientation, layout: HexLayout) -> IVec2
+{
+    // the line (row or column) that's offset, and the position along it.
+    let (along, line) = match orientation {
+        HexOrientation::Pointy => (pos.x, pos.y),
+        HexOrientation::Flat => (pos.y, pos.x),
+    };
+    let along = match layout {
+        HexLayout::OddOffset => along - (line - (line & 1)) / 2,
+        HexLayout::EvenOffset => along - (line + (line & 1)) / 2,
+        HexLayout::Axial => along,
+    };
+    match orientation {
+        HexOrientation::Pointy => IVec2::new(along, line),
+        HexOrientation::Flat => IVec2::new(line, along),
+    }
+}
+
+fn from_axial(axial: IVec2, orientation: HexOrientation, layout: HexLayout) -> IVec2
+{
+    let (along, line) = match orientation {
+        HexOrientation::Pointy => (axial.x, axial.y),
+        HexOrientation::Flat => (axial.y, axial.x),
+    };
+    let along = match layout {
+        HexLayout::OddOffset => along + (line - (line & 1)) / 2,
+        HexLayout::EvenOffset => along + (line + (line & 1)) / 2,
+        HexLayout::Axial => along,
+    };
+    match orientation {
+        HexOrientation::Pointy => IVec2::new(along, line),
+        HexOrientation::Flat => IVec2::new(line, along),
+    }
+}
+
+// round fractional axial coordinates to the nearest hexagon, via cube
+// coordinates.
+fn round_axial(axial: Vec2) -> IVec2
+{
+    let cube = Vec3::new(axial.x, axial.y, -axial.x - axial.y);
+    let rounded = cube.round();
+    let diff = (rounded - cube).abs();
+    if diff.x > diff.y && diff.x > diff.z {
+        IVec2::new((-rounded.y - rounded.z) as i32, rounded.y as i32)
+    } else if diff.y > diff.z {
+        IVec2::new(rounded.x as i32, (-rounded.x - rounded.z) as i32)
+    } else {
+        IVec2::new(rounded.x as i32, rounded.y as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const HEXES: [TileGrid; 6] = [TileGrid::Hex(HexOrientation::Pointy, HexLayout::OddOffset),
+                                  TileGrid::Hex(HexOrientation::Pointy, HexLayout::EvenOffset),
+                                  TileGrid::Hex(HexOrientation::Pointy, HexLayout::Axial),
+                                  TileGrid::Hex(HexOrientation::Flat, HexLayout::OddOffset),
+                                  TileGrid::Hex(HexOrientation::Flat, HexLayout::EvenOffset),
+                                  TileGrid::Hex(HexOrientation::Flat, HexLayout::Axial)];
+
+    fn positions() -> impl Iterator<Item = IVec2> { (-5..5).flat_map(|y| (-5..5).map(move |x| IVec2::new(x, y))) }
+
+    #[test]
+    fn axial_round_trip()
+    {
+        for grid in HEXES {
+            let TileGrid::Hex(orientation, layout) = grid else { unreachable!() };
+            for pos in positions() {
+                assert_eq!(from_axial(to_axial(pos, orientation, layout), orientation, layout), pos, "{grid:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn plane_round_trip()
+    {
+        let tile_size = Vec2::new(2., 1.5);
+        for grid in HEXES.into_iter().chain([TileGrid::Square, TileGrid::Isometric]) {
+            for pos in positions() {
+                let centre = grid.tile_to_plane(pos, tile_size);
+                assert_eq!(grid.plane_to_tile(centre, tile_size), pos, "{grid:?}");
+                // anywhere near the centre is still inside the tile.
+                let nudge = tile_size * Vec2::new(0.15, -0.15);
+                assert_eq!(grid.plane_to_tile(centre + nudge, tile_size), pos, "{grid:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn offset_rows()
+    {
+        let x = |grid: TileGrid, pos| grid.tile_to_plane(pos, Vec2::ONE).x;
+        let odd = TileGrid::Hex(HexOrientation::Pointy, HexLayout::OddOffset);
+        let even = TileGrid::Hex(HexOrientation::Pointy, HexLayout::EvenOffset);
+        assert_eq!(x(odd, IVec2::new(0, 1)) - x(odd, IVec2::ZERO), 0.5);
+        assert_eq!(x(even, IVec2::new(0, 1)) - x(even, IVec2::ZERO), -0.5);
+    }
+
+    #[test]
+    fn round_axial_picks_the_nearest_hex()
+    {
+        assert_eq!(round_axial(Vec2::new(0.1, -0.1)), IVec2::ZERO);
+        assert_eq!(round_axial(Vec2::new(0.9, 0.05)), IVec2::new(1, 0));
+        assert_eq!(round_axial(Vec2::new(-0.4, 0.9)), IVec2::new(0, 1));
+        // x + y + z must stay 0, so the coordinate furthest off is recomputed.
+        assert_eq!(round_axial(Vec2::new(0.6, 0.6)), IVec2::new(1, 0));
+    }
+}
@@ -0,0 +1,389 @@
+use bevy::asset::{embedded_asset, load_embedded_asset, uuid_handle, RenderAssetUsages};
+use bevy::core_pipeline::core_3d::Transparent3d;
+use bevy::ecs::lifecycle::HookContext;
+use bevy::ecs::system::lifetimeless::{Read, SRes};
+use bevy::ecs::system::SystemParamItem;
+use bevy::ecs::world::DeferredWorld;
+use bevy::math::Affine3A;
+use bevy::mesh::{Indices, MeshVertexBufferLayoutRef, PrimitiveTopology};
+use bevy::pbr::{MeshPipeline, MeshPipelineKey, SetMeshViewBindGroup, SetMeshViewBindingArrayBindGroup, ViewKeyCache};
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+use bevy::render::mesh::allocator::MeshAllocator;
+use bevy::render::mesh::{RenderMesh, RenderMeshBufferInfo};
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_phase::{AddRenderCommand, DrawFunctions, PhaseItem, PhaseItemExtraIndex, RenderCommand,
+                                 RenderCommandResult, SetItemPipeline, TrackedRenderPass, ViewSortedRenderPhases};
+use bevy::render::render_resource::binding_types::{sampler, storage_buffer_read_only, texture_2d};
+use bevy::render::render_resource::*;
+use bevy::render::renderer::{RenderDevice, RenderQueue};
+use bevy::render::sync_world::{MainEntity, TemporaryRenderEntity};
+use bevy::render::texture::GpuImage;
+use bevy::render::view::ExtractedView;
+use bevy::render::{Extract, ExtractSchedule, Render, RenderApp, RenderStartup, RenderSystems};
+
+use crate::{trimmed_pivot, HashableAlphaMode, Sprite3d, Sprite3dBuilder, Sprite3dFrames};
+
+// a unit quad from (0, 0) to (1, 1), which every instance is scaled from.
+const QUAD: Handle<Mesh> = uuid_handle!("5b0a7a43-3f5e-4c1e-9d2e-6c5f1b7e8a21");
+
+/// Draws a [`Sprite3d`] through the instanced renderer rather than as its own
+/// mesh. All instanced sprites sharing an image and alpha mode are drawn in a
+/// single draw call, for scenes with tens of thousands of sprites (grass,
+/// particles, crowds).
+///
+/// Sprites are set up with the same [`Sprite`] and [`Sprite3d`] components:
+/// the image, atlas index, flip, colour, pivot, frames, `pixels_per_metre`,
+/// `alpha_mode` and `uv_inset` are all used. They're drawn unlit and
+/// double-sided, and blended sprites aren't sorted among themselves, so prefer
+/// `AlphaMode::Mask`. Mesh-only options (`extrude_depth`, `tight_mesh`) don't
+/// apply. The placeholder mesh and material a `Sprite3d` starts with are
+/// removed.
+#[derive(Component, Clone, Copy, Debug, Default)]
+#[component(on_add = remove_sprite_mesh)]
+pub struct Sprite3dInstanced;
+
+// instanced sprites are drawn from the shared quad, so drop the mesh, material
+// and builder `Sprite3d` requires.
+fn remove_sprite_mesh(mut world: DeferredWorld, context: HookContext)
+{
+    world.commands()
+         .entity(context.entity)
+         .remove::<(Mesh3d, MeshMaterial3d<StandardMaterial>, Sprite3dBuilder)>();
+}
+
+pub(crate) struct InstancedSpritePlugin;
+
+impl Plugin for InstancedSpritePlugin
+{
+    fn build(&self, app: &mut App)
+    {
+        embedded_asset!(app, "instanced.wgsl");
+        app.add_systems(Startup, add_quad);
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app.add_render_command::<Transparent3d, DrawInstancedSprites>()
+                  .init_resource::<SpecializedMeshPipelines<InstancedPipeline>>()
+                  .add_systems(RenderStartup, init_instanced_pipeline)
+                  .add_systems(ExtractSchedule, extract_instanced_sprites)
+                  .add_systems(Render,
+                               (queue_instanced_sprites.in_set(RenderSystems::QueueMeshes),
+                                prepare_instanced_sprites.in_set(RenderSystems::PrepareBindGroups)));
+    }
+}
+
+fn add_quad(mut meshes: ResMut<Assets<Mesh>>)
+{
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::RENDER_WORLD);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0., 0., 0.], [1., 0., 0.], [1., 1., 0.], [0., 1., 0.]]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0., 0., 1.]; 4]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0., 1.], [1., 1.], [1., 0.], [0., 0.]]);
+    mesh.insert_indices(Indices::U32(vec![0, 1, 2, 0, 2, 3]));
+    let _ = meshes.insert(&QUAD, mesh);
+}
+
+#[derive(ShaderType, Clone, Copy)]
+struct SpriteInstance
+{
+    model_x: Vec4,
+    model_y: Vec4,
+    model_z: Vec4,
+    uv_rect: Vec4,
+    colour:  Vec4,
+}
+
+#[derive(ShaderType, Default)]
+struct InstancedSprites
+{
+    alpha_cutoff: f32,
+    #[shader(size(runtime))]
+    instances:    Vec<SpriteInstance>,
+}
+
+// the instances of every sprite sharing an image and alpha mode, extracted
+// into the render world for the frame.
+#[derive(Component)]
+struct InstancedBatch
+{
+    image:      AssetId<Image>,
+    alpha_mode: AlphaMode,
+    sprites:    InstancedSprites,
+    count:      u32,
+    // the middle of the batch, for sorting it against other transparent
+    // things.
+    centre:     Vec3,
+}
+
+#[derive(Component)]
+struct InstancedBindGroup(BindGroup);
+
+#[allow(clippy::type_complexity)]
+fn extract_instanced_sprites(mut commands: Commands,
+                             images: Extract<Res<Assets<Image>>>,
+                             layouts: Extract<Res<Assets<TextureAtlasLayout>>>,
+                             frames: Extract<Res<Assets<Sprite3dFrames>>>,
+                             sprites: Extract<Query<(&Sprite, &Sprite3d, &GlobalTransform, &InheritedVisibility),
+                                                    With<Sprite3dInstanced>>>)
+{
+    let mut batches: HashMap<(AssetId<Image>, HashableAlphaMode), (Vec<SpriteInstance>, Vec3)> = HashMap::default();
+
+    for (sprite, sprite3d, global, visibility) in sprites.iter() {
+        if !visibility.get() {
+            continue;
+        }
+        let Some(image) = images.get(&sprite.image) else {
+            continue;
+        };
+        let image_size = image.size();
+        let (rect, index) = match &sprite.texture_atlas {
+            Some(atlas) => {
+                let Some(rect) = layouts.get(&atlas.layout).and_then(|l| l.textures.get(atlas.index)) else {
+                    continue;
+                };
+                (*rect, Some(atlas.index))
+            }
+            None => (URect::from_corners(UVec2::ZERO, image_size), None),
+        };
+
+        // as in `bundle_builder`, frames can override and trim the pivot.
+        let mut pivot = sprite3d.pivot.unwrap_or(Vec2::splat(0.5));
+        if let (Some(frames), Some(i)) = (sprite3d.frames.as_ref().and_then(|f| frames.get(f)), index) {
+            pivot = frames.pivot(i).unwrap_or(pivot);
+            if let Some(trim) = frames.trim(i) {
+                pivot = trimmed_pivot(pivot, rect, trim);
+            }
+        }
+        let size = rect.size().as_vec2() / sprite3d.pixels_per_metre;
+        let model = global.affine() * Affine3A::from_scale_rotation_translation(size.extend(1.), Quat::IDENTITY, (-pivot * size).extend(0.));
+        let model = Mat4::from(model);
+
+        let texel = image_size.as_vec2().recip();
+        let inset = (texel * sprite3d.uv_inset.max(0.)).min(rect.size().as_vec2() * texel / 2.);
+        let (mut min, mut max) = (rect.min.as_vec2() * texel + inset, rect.max.as_vec2() * texel - inset);
+        if sprite.flip_x {
+            std::mem::swap(&mut min.x, &mut max.x);
+        }
+        if sprite.flip_y {
+            std::mem::swap(&mut min.y, &mut max.y);
+        }
+
+        let (instances, centre) = batches.entry((sprite.image.id(), HashableAlphaMode(sprite3d.alpha_mode))).or_default();
+        *centre += global.translation();
+        instances.push(SpriteInstance { model_x: model.row(0),
+                                        model_y: model.row(1),
+                                        model_z: model.row(2),
+                                        uv_rect: Vec4::new(min.x, min.y, max.x, max.y),
+                                        colour:  LinearRgba::from(sprite.color).to_vec4() });
+    }
+
+    for ((image, HashableAlphaMode(alpha_mode)), (instances, centre)) in batches {
+        let alpha_cutoff = match alpha_mode {
+            AlphaMode::Mask(cutoff) => cutoff,
+            _ => 0.,
+        };
+        commands.spawn((InstancedBatch { image,
+                                         alpha_mode,
+                                         count: instances.len() as u32,
+                                         centre: centre / instances.len() as f32,
+                                         sprites: InstancedSprites { alpha_cutoff, instances } },
+                        TemporaryRenderEntity));
+    }
+}
+
+#[derive(Resource)]
+struct InstancedPipeline
+{
+    mesh_pipeline: MeshPipeline,
+    layout:        BindGroupLayoutDescriptor,
+    shader:        Handle<Shader>,
+}
+
+fn init_instanced_pipeline(mut commands: Commands, asset_server: Res<AssetServer>, mesh_pipeline: Res<MeshPipeline>)
+{
+    let layout = BindGroupLayoutDescriptor::new("sprite3d_instanced_layout",
+                                                &BindGroupLayoutEntries::sequential(ShaderStages::VERTEX_FRAGMENT,
+                                                                                    (storage_buffer_read_only::<InstancedSprites>(false),
+                                                                                     texture_2d(TextureSampleType::Float { filterable: true }),
+                                                                                     sampler(SamplerBindingType::Filtering))));
+    commands.insert_resource(InstancedPipeline { mesh_pipeline: mesh_pipeline.clone(),
+                                                 layout,
+                                                 shader: load_embedded_asset!(asset_server.as_ref(), "instanced.wgsl") });
+}
+
+// the mesh pipeline's key, plus whether the batch blends additively, which
+// shares its blending with premultiplied alpha.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct InstancedPipelineKey
+{
+    mesh:     MeshPipelineKey,
+    additive: bool,
+}
+
+impl SpecializedMeshPipeline for InstancedPipeline
+{
+    type Key = InstancedPipelineKey;
+
+    fn specialize(&self, key: Self::Key, layout: &MeshVertexBufferLayoutRef) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError>
+    {
+        // start from the mesh pipeline, for the view bindings, blending and
+        // render targets, and swap in the sprites for the mesh bindings.
+        let mut descriptor = self.mesh_pipeline.specialize(key.mesh, layout)?;
+        descriptor.label = Some("sprite3d_instanced_pipeline".into());
+        descriptor.layout.truncate(2);
+        descriptor.layout.push(self.layout.clone());
+        descriptor.vertex.shader = self.shader.clone();
+        descriptor.primitive.cull_mode = None;
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            fragment.shader = self.shader.clone();
+            if key.additive {
+                fragment.shader_defs.push("BLEND_ADD".into());
+            }
+        }
+        Ok(descriptor)
+    }
+}
+
+// the mesh pipeline's blending for an alpha mode.
+fn alpha_mode_key(alpha_mode: AlphaMode) -> MeshPipelineKey
+{
+    match alpha_mode {
+        AlphaMode::Opaque => MeshPipelineKey::NONE,
+        AlphaMode::Mask(_) => MeshPipelineKey::MAY_DISCARD,
+        AlphaMode::Blend => MeshPipelineKey::BLEND_ALPHA,
+        AlphaMode::Premultiplied | AlphaMode::Add => MeshPipelineKey::BLEND_PREMULTIPLIED_ALPHA,
+        AlphaMode::Multiply => MeshPipelineKey::BLEND_MULTIPLY,
+        AlphaMode::AlphaToCoverage => MeshPipelineKey::BLEND_ALPHA_TO_COVERAGE,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn queue_instanced_sprites(draw_functions: Res<DrawFunctions<Transparent3d>>,
+                           pipeline: Res<InstancedPipeline>,
+                           mut pipelines: ResMut<SpecializedMeshPipelines<InstancedPipeline>>,
+                           pipeline_cache: Res<PipelineCache>,
+                           meshes: Res<RenderAssets<RenderMesh>>,
+                           view_key_cache: Res<ViewKeyCache>,
+                           batches: Query<(Entity, &InstancedBatch)>,
+                           mut phases: ResMut<ViewSortedRenderPhases<Transparent3d>>,
+                           views: Query<&ExtractedView>)
+{
+    let draw_function = draw_functions.read().id::<DrawInstancedSprites>();
+    let Some(quad) = meshes.get(QUAD.id()) else {
+        return;
+    };
+
+    for view in views.iter() {
+        let (Some(phase), Some(view_key)) = (phases.get_mut(&view.retained_view_entity),
+                                             view_key_cache.get(&view.retained_view_entity)) else {
+            continue;
+        };
+        let rangefinder = view.rangefinder3d();
+        for (entity, batch) in batches.iter() {
+            let key = InstancedPipelineKey { mesh:     *view_key
+                                                       | MeshPipelineKey::from_primitive_topology(quad.primitive_topology())
+                                                       | alpha_mode_key(batch.alpha_mode),
+                                             additive: batch.alpha_mode == AlphaMode::Add };
+            let Ok(pipeline) = pipelines.specialize(&pipeline_cache, &pipeline, key, &quad.layout) else {
+                continue;
+            };
+            phase.add(Transparent3d { entity: (entity, MainEntity::from(Entity::PLACEHOLDER)),
+                                      pipeline,
+                                      draw_function,
+                                      distance: rangefinder.distance(&batch.centre),
+                                      batch_range: 0..1,
+                                      extra_index: PhaseItemExtraIndex::None,
+                                      indexed: true });
+        }
+    }
+}
+
+fn prepare_instanced_sprites(mut commands: Commands,
+                             mut batches: Query<(Entity, &mut InstancedBatch)>,
+                             images: Res<RenderAssets<GpuImage>>,
+                             pipeline: Res<InstancedPipeline>,
+                             pipeline_cache: Res<PipelineCache>,
+                             device: Res<RenderDevice>,
+                             queue: Res<RenderQueue>)
+{
+    for (entity, mut batch) in batches.iter_mut() {
+        let Some(image) = images.get(batch.image) else {
+            continue;
+        };
+        let mut buffer = StorageBuffer::from(std::mem::take(&mut batch.sprites));
+        buffer.write_buffer(&device, &queue);
+        let Some(sprites) = buffer.binding() else {
+            continue;
+        };
+        let bind_group = device.create_bind_group("sprite3d_instanced_bind_group",
+                                                  &pipeline_cache.get_bind_group_layout(&pipeline.layout),
+                                                  &BindGroupEntries::sequential((sprites, &image.texture_view, &image.sampler)));
+        commands.entity(entity).insert(InstancedBindGroup(bind_group));
+    }
+}
+
+type DrawInstancedSprites = (SetItemPipeline,
+                             SetMeshViewBindGroup<0>,
+                             SetMeshViewBindingArrayBindGroup<1>,
+                             SetInstancedBindGroup<2>,
+                             DrawInstancedQuads);
+
+struct SetInstancedBindGroup<const I: usize>;
+
+impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetInstancedBindGroup<I>
+{
+    type Param = ();
+    type ViewQuery = ();
+    type ItemQuery = Read<InstancedBindGroup>;
+
+    fn render<'w>(_item: &P,
+                  _view: (),
+                  bind_group: Option<&'w InstancedBindGroup>,
+                  _param: SystemParamItem<'w, '_, Self::Param>,
+                  pass: &mut TrackedRenderPass<'w>)
+                  -> RenderCommandResult
+    {
+        let Some(bind_group) = bind_group else {
+            return RenderCommandResult::Skip;
+        };
+        pass.set_bind_group(I, &bind_group.0, &[]);
+        RenderCommandResult::Success
+    }
+}
+
+struct DrawInstancedQuads;
+
+impl<P: PhaseItem> RenderCommand<P> for DrawInstancedQuads
+{
+    type Param = (SRes<RenderAssets<RenderMesh>>, SRes<MeshAllocator>);
+    type ViewQuery = ();
+    type ItemQuery = Read<InstancedBatch>;
+
+    fn render<'w>(_item: &P,
+                  _view: (),
+                  batch: Option<&'w InstancedBatch>,
+                  (meshes, allocator): SystemParamItem<'w, '_, Self::Param>,
+                  pass: &mut TrackedRenderPass<'w>)
+                  -> RenderCommandResult
+    {
+        let allocator = allocator.into_inner();
+        let (Some(batch), Some(quad)) = (batch, meshes.into_inner().get(QUAD.id())) else {
+            return RenderCommandResult::Skip;
+        };
+        let (Some(vertices), RenderMeshBufferInfo::Indexed { index_format, count }) =
+            (allocator.mesh_vertex_slice(&QUAD.id()), &quad.buffer_info) else {
+            return RenderCommandResult::Skip;
+        };
+        let Some(indices) = allocator.mesh_index_slice(&QUAD.id()) else {
+            return RenderCommandResult::Skip;
+        };
+
+        pass.set_vertex_buffer(0, vertices.buffer.slice(..));
+        pass.set_index_buffer(indices.buffer.slice(..), *index_format);
+        pass.draw_indexed(indices.range.start..indices.range.start + count,
+                          vertices.range.start as i32,
+                          0..batch.count);
+        RenderCommandResult::Success
+    }
+}
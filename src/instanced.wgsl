@@ -0,0 +1,65 @@
+#import bevy_pbr::mesh_view_bindings::view
+
+struct SpriteInstance {
+    // the rows of the sprite's model matrix, which maps the unit quad to world
+    // space.
+    model_x: vec4<f32>,
+    model_y: vec4<f32>,
+    model_z: vec4<f32>,
+    // top-left and bottom-right UVs, swapped to flip.
+    uv_rect: vec4<f32>,
+    colour: vec4<f32>,
+}
+
+struct InstancedSprites {
+    alpha_cutoff: f32,
+    instances: array<SpriteInstance>,
+}
+
+@group(2) @binding(0) var<storage, read> sprites: InstancedSprites;
+@group(2) @binding(1) var sprite_texture: texture_2d<f32>;
+@group(2) @binding(2) var sprite_sampler: sampler;
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) colour: vec4<f32>,
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let sprite = sprites.instances[vertex.instance_index];
+    let local = vec4<f32>(vertex.position, 1.0);
+    let world = vec3<f32>(dot(sprite.model_x, local), dot(sprite.model_y, local), dot(sprite.model_z, local));
+
+    var out: VertexOutput;
+    out.clip_position = view.clip_from_world * vec4<f32>(world, 1.0);
+    out.uv = mix(sprite.uv_rect.xy, sprite.uv_rect.zw, vertex.uv);
+    out.colour = sprite.colour;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    var colour = textureSample(sprite_texture, sprite_sampler, in.uv) * in.colour;
+#ifdef MAY_DISCARD
+    if colour.a < sprites.alpha_cutoff {
+        discard;
+    }
+#endif
+    // as bevy's `premultiply_alpha`: premultiplied sprites are left as they
+    // are, and additive ones drop their alpha so the destination is kept whole.
+#ifdef BLEND_ADD
+    colour = vec4<f32>(colour.rgb * colour.a, 0.0);
+#endif
+#ifdef BLEND_MULTIPLY
+    colour = vec4<f32>(colour.rgb * colour.a, colour.a);
+#endif
+    return colour;
+}
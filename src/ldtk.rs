@@ -0,0 +1,936 @@
+use bevy::asset::{io::Reader, AssetLoader, LoadContext, ParseAssetPathError, ReadAssetBytesError};
+use bevy::platform::collections::{HashMap, HashSet};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::fmt;
+
+use crate::level::QuadMesh;
+use crate::tilemap::tile_uvs;
+use crate::{Sprite3d, Sprite3dCaches, Sprite3dTilemap, TilemapPlane, DEFAULT_ALPHA_MODE};
+
+/// Loads [LDtk](https://ldtk.io) projects as a [`Sprite3dLdtkProject`], along
+/// with their tileset images and any levels saved as separate files. Spawn
+/// one with [`Sprite3dLdtk`].
+///
+/// Tile layers, auto-layers (including those of IntGrid layers) and entity
+/// instances are kept; tilesets are sliced into atlas layouts using their
+/// spacing and padding.
+///
+/// Registered for the `.ldtk` extension.
+#[derive(Default, TypePath)]
+pub struct LdtkLoader;
+
+/// A tileset from an LDtk project, sliced into an atlas layout where each
+/// tile's atlas index is its LDtk tile id. Rects used by entities that don't
+/// line up with the grid are added after the grid tiles.
+#[derive(Clone, Debug)]
+pub struct LdtkTileset
+{
+    pub uid:        i32,
+    pub identifier: String,
+    pub image:      Handle<Image>,
+    pub layout:     Handle<TextureAtlasLayout>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LdtkLayerKind
+{
+    IntGrid,
+    Entities,
+    Tiles,
+    AutoLayer,
+}
+
+/// A tile placed in a layer.
+#[derive(Clone, Copy, Debug)]
+pub struct LdtkTile
+{
+    /// The tile's top-left corner within the layer, in pixels.
+    pub position: IVec2,
+    /// The atlas index within the layer's tileset.
+    pub index:    usize,
+    pub flip_x:   bool,
+    pub flip_y:   bool,
+}
+
+/// One layer of an [`LdtkLevel`].
+#[derive(Clone, Debug)]
+pub struct LdtkLayer
+{
+    pub identifier: String,
+    pub kind:       LdtkLayerKind,
+
+    /// The size of the layer, in cells.
+    pub size:       UVec2,
+    pub grid_size:  u32,
+
+    /// The layer's offset within the level, in pixels.
+    pub offset:     Vec2,
+    pub visible:    bool,
+
+    /// Index into [`Sprite3dLdtkProject::tilesets`] of the tileset used by
+    /// this layer's tiles.
+    pub tileset:    Option<usize>,
+
+    /// Placed and auto-layer tiles, in the order LDtk draws them.
+    pub tiles:      Vec<LdtkTile>,
+
+    /// IntGrid values, row by row, `0` for empty cells.
+    pub int_grid:   Vec<i32>,
+    pub entities:   Vec<LdtkEntity>,
+}
+
+impl LdtkLayer
+{
+    /// The IntGrid value at `cell`, or `0` outside the layer.
+    pub fn int_value(&self, cell: IVec2) -> i32
+    {
+        if cell.x < 0 || cell.y < 0 || cell.x >= self.size.x as i32 || cell.y >= self.size.y as i32 {
+            return 0;
+        }
+        self.int_grid.get((cell.y * self.size.x as i32 + cell.x) as usize).copied().unwrap_or(0)
+    }
+}
+
+/// An entity instance from an entity layer. Spawned levels attach this to
+/// each entity, so its fields can be read by game code.
+#[derive(Component, Clone, Debug)]
+pub struct LdtkEntity
+{
+    pub identifier: String,
+    pub iid:        String,
+    pub tags:       Vec<String>,
+
+    /// The position of the entity's pivot within its level, in pixels.
+    pub position:   Vec2,
+    pub size:       Vec2,
+
+    /// The pivot, from the top-left of the entity.
+    pub pivot:      Vec2,
+
+    /// The tileset index and atlas index of the entity's tile, if it has one.
+    pub tile:       Option<(usize, usize)>,
+
+    /// Field values, as LDtk saved them.
+    pub fields:     HashMap<String, serde_json::Value>,
+}
+
+/// One level of a [`Sprite3dLdtkProject`].
+#[derive(Clone, Debug)]
+pub struct LdtkLevel
+{
+    pub identifier:     String,
+    pub iid:            String,
+
+    /// The level's top-left corner in the world, in pixels.
+    pub world_position: IVec2,
+
+    /// The size of the level, in pixels.
+    pub size:           UVec2,
+
+    /// Layers, bottom to top.
+    pub layers:         Vec<LdtkLayer>,
+    pub fields:         HashMap<String, serde_json::Value>,
+}
+
+/// A project loaded by [`LdtkLoader`].
+#[derive(Asset, TypePath, Debug)]
+pub struct Sprite3dLdtkProject
+{
+    pub tilesets: Vec<LdtkTileset>,
+
+    /// Levels from every world in the project.
+    pub levels:   Vec<LdtkLevel>,
+
+    #[dependency]
+    images:       Vec<Handle<Image>>,
+}
+
+impl Sprite3dLdtkProject
+{
+    /// The level called `identifier`, if the project has one.
+    pub fn level(&self, identifier: &str) -> Option<&LdtkLevel>
+    {
+        self.levels.iter().find(|l| l.identifier == identifier)
+    }
+}
+
+
+/// Errors from [`LdtkLoader`].
+#[derive(Debug)]
+pub enum LdtkLoaderError
+{
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Path(ParseAssetPathError),
+    /// A level saved as a separate file couldn't be read.
+    Level(ReadAssetBytesError),
+}
+
+impl fmt::Display for LdtkLoaderError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Self::Io(e) => write!(f, "could not read ldtk project: {e}"),
+            Self::Json(e) => write!(f, "could not parse ldtk project: {e}"),
+            Self::Path(e) => write!(f, "invalid path in ldtk project: {e}"),
+            Self::Level(e) => write!(f, "could not read ldtk level: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for LdtkLoaderError {}
+
+impl From<std::io::Error> for LdtkLoaderError
+{
+    fn from(e: std::io::Error) -> Self { Self::Io(e) }
+}
+
+impl From<serde_json::Error> for LdtkLoaderError
+{
+    fn from(e: serde_json::Error) -> Self { Self::Json(e) }
+}
+
+impl From<ParseAssetPathError> for LdtkLoaderError
+{
+    fn from(e: ParseAssetPathError) -> Self { Self::Path(e) }
+}
+
+impl From<ReadAssetBytesError> for LdtkLoaderError
+{
+    fn from(e: ReadAssetBytesError) -> Self { Self::Level(e) }
+}
+
+
+// ---------------------------------------------------------------------------
+// The parts of the LDtk json format we use.
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct JsonProject
+{
+    defs:   JsonDefs,
+    #[serde(default)]
+    levels: Vec<JsonLevel>,
+    /// only used by multi-world projects.
+    #[serde(default)]
+    worlds: Vec<JsonWorld>,
+}
+
+#[derive(Deserialize, Debug)]
+struct JsonWorld
+{
+    #[serde(default)]
+    levels: Vec<JsonLevel>,
+}
+
+#[derive(Deserialize, Debug)]
+struct JsonDefs
+{
+    #[serde(default)]
+    tilesets: Vec<JsonTileset>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct JsonTileset
+{
+    uid:            i32,
+    identifier:     String,
+    /// `None` for LDtk's built-in icons, which we can't load.
+    rel_path:       Option<String>,
+    tile_grid_size: u32,
+    #[serde(default)]
+    spacing:        u32,
+    #[serde(default)]
+    padding:        u32,
+    #[serde(rename = "__cWid")]
+    columns:        u32,
+    #[serde(rename = "__cHei")]
+    rows:           u32,
+    px_wid:         u32,
+    px_hei:         u32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct JsonLevel
+{
+    identifier:        String,
+    iid:               String,
+    world_x:           i32,
+    world_y:           i32,
+    px_wid:            u32,
+    px_hei:            u32,
+    #[serde(default)]
+    field_instances:   Vec<JsonField>,
+    /// `None` when the level is saved separately, at `external_rel_path`.
+    layer_instances:   Option<Vec<JsonLayer>>,
+    external_rel_path: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct JsonLayer
+{
+    #[serde(rename = "__identifier")]
+    identifier:       String,
+    #[serde(rename = "__type")]
+    kind:             String,
+    #[serde(rename = "__cWid")]
+    columns:          u32,
+    #[serde(rename = "__cHei")]
+    rows:             u32,
+    #[serde(rename = "__gridSize")]
+    grid_size:        u32,
+    #[serde(rename = "__pxTotalOffsetX", default)]
+    offset_x:         f32,
+    #[serde(rename = "__pxTotalOffsetY", default)]
+    offset_y:         f32,
+    #[serde(rename = "__tilesetDefUid")]
+    tileset:          Option<i32>,
+    #[serde(default = "yes")]
+    visible:          bool,
+    #[serde(default)]
+    int_grid_csv:     Vec<i32>,
+    #[serde(default)]
+    auto_layer_tiles: Vec<JsonTile>,
+    #[serde(default)]
+    grid_tiles:       Vec<JsonTile>,
+    #[serde(default)]
+    entity_instances: Vec<JsonEntity>,
+}
+
+fn yes() -> bool { true }
+
+#[derive(Deserialize, Debug)]
+struct JsonTile
+{
+    px: [i32; 2],
+    /// bit 0 flips x, bit 1 flips y.
+    #[serde(default)]
+    f:  u32,
+    t:  usize,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct JsonEntity
+{
+    #[serde(rename = "__identifier")]
+    identifier:      String,
+    iid:             String,
+    #[serde(rename = "__tags", default)]
+    tags:            Vec<String>,
+    #[serde(rename = "__pivot")]
+    pivot:           [f32; 2],
+    #[serde(rename = "__tile")]
+    tile:            Option<JsonTileRect>,
+    width:           f32,
+    height:          f32,
+    px:              [f32; 2],
+    #[serde(default)]
+    field_instances: Vec<JsonField>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct JsonTileRect
+{
+    tileset_uid: i32,
+    x:           u32,
+    y:           u32,
+    w:           u32,
+    h:           u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct JsonField
+{
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(rename = "__value")]
+    value:      serde_json::Value,
+}
+
+fn fields(fields: Vec<JsonField>) -> HashMap<String, serde_json::Value>
+{
+    fields.into_iter().map(|f| (f.identifier, f.value)).collect()
+}
+
+
+impl AssetLoader for LdtkLoader
+{
+    type Asset = Sprite3dLdtkProject;
+    type Settings = ();
+    type Error = LdtkLoaderError;
+
+    async fn load(&self,
+                  reader: &mut dyn Reader,
+                  _settings: &(),
+                  load_context: &mut LoadContext<'_>)
+                  -> Result<Sprite3dLdtkProject, LdtkLoaderError>
+    {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let project: JsonProject = serde_json::from_slice(&bytes)?;
+
+        let mut json_levels = project.levels;
+        json_levels.extend(project.worlds.into_iter().flat_map(|w| w.levels));
+
+        // pull in levels saved in their own files.
+        for level in &mut json_levels {
+            if level.layer_instances.is_some() {
+                continue;
+            }
+            let Some(rel_path) = &level.external_rel_path else { continue };
+            let path = load_context.path().resolve_embed(rel_path)?;
+            let bytes = load_context.read_asset_bytes(path).await?;
+            *level = serde_json::from_slice(&bytes)?;
+        }
+
+        let mut layouts: Vec<TextureAtlasLayout> = project.defs.tilesets.iter().map(|ts| {
+            TextureAtlasLayout::from_grid(UVec2::splat(ts.tile_grid_size),
+                                          ts.columns,
+                                          ts.rows,
+                                          Some(UVec2::splat(ts.spacing)),
+                                          Some(UVec2::splat(ts.padding)))
+        }).collect();
+        let mut levels: Vec<LdtkLevel> =
+            json_levels.into_iter().map(|level| parse_level(level, &project.defs.tilesets, &mut layouts)).collect();
+
+        let mut tilesets = Vec::new();
+        for (ts, mut layout) in project.defs.tilesets.iter().zip(layouts) {
+            // built-in icon tilesets have no image to load.
+            let Some(rel_path) = &ts.rel_path else { continue };
+            layout.size = UVec2::new(ts.px_wid, ts.px_hei);
+            let image = load_context.load(load_context.path().resolve_embed(rel_path)?);
+            let layout = load_context.add_labeled_asset(format!("{}/layout", ts.identifier), layout);
+            tilesets.push(LdtkTileset { uid: ts.uid, identifier: ts.identifier.clone(), image, layout });
+        }
+
+        // tileset indices were into every tileset, so remap them to the ones
+        // that loaded.
+        let remap: Vec<Option<usize>> = project.defs.tilesets.iter().scan(0, |n, ts| {
+            Some(ts.rel_path.as_ref().map(|_| { *n += 1; *n - 1 }))
+        }).collect();
+        for layer in levels.iter_mut().flat_map(|l| &mut l.layers) {
+            layer.tileset = layer.tileset.and_then(|i| remap[i]);
+            for entity in &mut layer.entities {
+                entity.tile = entity.tile.and_then(|(ts, index)| Some((remap[ts]?, index)));
+            }
+        }
+
+        let images = tilesets.iter().map(|t| t.image.clone()).collect();
+        Ok(Sprite3dLdtkProject { tilesets, levels, images })
+    }
+
+    fn extensions(&self) -> &[&str] { &["ldtk"] }
+}
+
+// a level's layers, bottom to top, with any tiles its entities use added to
+// `layouts` (one per tileset, in `tilesets` order).
+fn parse_level(level: JsonLevel, tilesets: &[JsonTileset], layouts: &mut [TextureAtlasLayout]) -> LdtkLevel
+{
+    let tileset_index = |uid: i32| tilesets.iter().position(|ts| ts.uid == uid);
+
+    let mut layers = Vec::new();
+    // ldtk lists layers top to bottom.
+    for layer in level.layer_instances.unwrap_or_default().into_iter().rev() {
+        let kind = match layer.kind.as_str() {
+            "IntGrid" => LdtkLayerKind::IntGrid,
+            "Entities" => LdtkLayerKind::Entities,
+            "Tiles" => LdtkLayerKind::Tiles,
+            _ => LdtkLayerKind::AutoLayer,
+        };
+        let tiles = layer.grid_tiles.iter().chain(&layer.auto_layer_tiles).map(|t| {
+            LdtkTile { position: IVec2::from(t.px),
+                       index:    t.t,
+                       flip_x:   t.f & 1 != 0,
+                       flip_y:   t.f & 2 != 0, }
+        }).collect();
+
+        let entities = layer.entity_instances.into_iter().map(|e| {
+            // entity tiles can cover any rect of a tileset, so find or add it
+            // in the layout.
+            let tile = e.tile.and_then(|r| {
+                let tileset = tileset_index(r.tileset_uid)?;
+                let rect = URect::new(r.x, r.y, r.x + r.w, r.y + r.h);
+                let layout = &mut layouts[tileset];
+                let index = match layout.textures.iter().position(|&t| t == rect) {
+                    Some(index) => index,
+                    None => layout.add_texture(rect),
+                };
+                Some((tileset, index))
+            });
+            LdtkEntity { identifier: e.identifier,
+                         iid:        e.iid,
+                         tags:       e.tags,
+                         position:   Vec2::from(e.px),
+                         size:       Vec2::new(e.width, e.height),
+                         pivot:      Vec2::from(e.pivot),
+                         tile,
+                         fields:     fields(e.field_instances) }
+        }).collect();
+
+        layers.push(LdtkLayer { identifier: layer.identifier,
+                                kind,
+                                size: UVec2::new(layer.columns, layer.rows),
+                                grid_size: layer.grid_size,
+                                offset: Vec2::new(layer.offset_x, layer.offset_y),
+                                visible: layer.visible,
+                                tileset: layer.tileset.and_then(tileset_index),
+                                tiles,
+                                int_grid: layer.int_grid_csv,
+                                entities });
+    }
+
+    LdtkLevel { identifier:     level.identifier,
+                iid:            level.iid,
+                world_position: IVec2::new(level.world_x, level.world_y),
+                size:           UVec2::new(level.px_wid, level.px_hei),
+                layers,
+                fields:         fields(level.field_instances) }
+}
+
+
+/// How to extrude cells of an IntGrid value into walls.
+#[derive(Clone, Copy, Debug)]
+pub struct LdtkWall
+{
+    /// The height of the wall, in world units.
+    pub height: f32,
+
+    /// The atlas index (in the IntGrid layer's auto-layer tileset) to draw
+    /// cells with that have no auto-layer tile of their own.
+    pub tile:   Option<usize>,
+}
+
+impl LdtkWall
+{
+    pub fn new(height: f32) -> Self { Self { height, tile: None } }
+
+    pub fn with_tile(mut self, tile: usize) -> Self
+    {
+        self.tile = Some(tile);
+        self
+    }
+}
+
+/// Spawns the levels of a [`Sprite3dLdtkProject`] as children of this entity,
+/// each one only once the active camera comes within `spawn_distance` of
+/// it. The world's origin is at the entity's origin.
+///
+/// - tile and auto-layers become a [`Sprite3dTilemap`] (or a `Sprite3d` per
+///   tile if `merge` is off), with stacked tiles split into extra tilemaps.
+/// - IntGrid values with an [`LdtkWall`] are extruded into walls, textured
+///   with the layer's auto-layer tiles.
+/// - entity instances are spawned with an [`LdtkEntity`] component, and a
+///   `Sprite3d` if they have a tile, standing upright on `XZ` maps.
+///
+/// Tilemaps and sprites all share meshes and materials through
+/// [`Sprite3dCaches`].
+#[derive(Component, Clone, Debug)]
+#[require(Transform, Visibility)]
+pub struct Sprite3dLdtk
+{
+    pub project:          Handle<Sprite3dLdtkProject>,
+
+    /// Pixels in the project per world-space metre.
+    pub pixels_per_metre: f32,
+    pub plane:            TilemapPlane,
+
+    /// The distance between layers along the plane's normal.
+    pub layer_spacing:    f32,
+
+    /// Merge tiles into `Sprite3dTilemap` chunks (default), rather than
+    /// spawning a `Sprite3d` per tile.
+    pub merge:            bool,
+
+    /// How close, within the plane, the camera has to get to a level for it
+    /// to be spawned. `32` by default.
+    pub spawn_distance:   f32,
+
+    /// Walls to extrude, keyed by IntGrid value.
+    pub walls:            HashMap<i32, LdtkWall>,
+
+    spawned:              HashSet<usize>,
+}
+
+/// Marks the root entity of a spawned LDtk level.
+#[derive(Component, Clone, Debug)]
+pub struct Sprite3dLdtkLevel
+{
+    /// Index into [`Sprite3dLdtkProject::levels`].
+    pub index: usize,
+    pub iid:   String,
+}
+
+impl Sprite3dLdtk
+{
+    pub fn new(project: Handle<Sprite3dLdtkProject>, pixels_per_metre: f32) -> Self
+    {
+        Self { project,
+               pixels_per_metre,
+               plane: TilemapPlane::XZ,
+               layer_spacing: 0.01,
+               merge: true,
+               spawn_distance: 32.,
+               walls: HashMap::default(),
+               spawned: HashSet::default() }
+    }
+
+    pub fn with_plane(mut self, plane: TilemapPlane) -> Self
+    {
+        self.plane = plane;
+        self
+    }
+
+    pub fn with_layer_spacing(mut self, layer_spacing: f32) -> Self
+    {
+        self.layer_spacing = layer_spacing;
+        self
+    }
+
+    pub fn with_merge(mut self, merge: bool) -> Self
+    {
+        self.merge = merge;
+        self
+    }
+
+    pub fn with_spawn_distance(mut self, spawn_distance: f32) -> Self
+    {
+        self.spawn_distance = spawn_distance;
+        self
+    }
+
+    /// Extrudes cells with IntGrid value `value` into walls.
+    pub fn with_wall(mut self, value: i32, wall: LdtkWall) -> Self
+    {
+        self.walls.insert(value, wall);
+        self
+    }
+
+    /// Whether level `index` has been spawned yet.
+    pub fn is_spawned(&self, index: usize) -> bool { self.spawned.contains(&index) }
+
+    // a position in pixels, `height` along the normal, to local space.
+    fn to_local(&self, pixels: Vec2, height: f32) -> Vec3
+    {
+        let (right, down, normal) = self.plane.axes();
+        let p = pixels / self.pixels_per_metre;
+        right * p.x + down * p.y + normal * height
+    }
+
+    // rotates a +Z facing quad to lie flat in the plane.
+    fn flat(&self) -> Quat
+    {
+        match self.plane {
+            TilemapPlane::XZ => Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2),
+            TilemapPlane::XY => Quat::IDENTITY,
+        }
+    }
+}
+
+// Spawn the levels of each `Sprite3dLdtk` that the camera has come close to.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn spawn_ldtk_levels(mut commands: Commands,
+                                asset_server: Res<AssetServer>,
+                                projects: Res<Assets<Sprite3dLdtkProject>>,
+                                images: Res<Assets<Image>>,
+                                atlas_layouts: Res<Assets<TextureAtlasLayout>>,
+                                mut caches: ResMut<Sprite3dCaches>,
+                                mut meshes: ResMut<Assets<Mesh>>,
+                                mut materials: ResMut<Assets<StandardMaterial>>,
+                                cameras: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+                                mut query: Query<(Entity, &mut Sprite3dLdtk, &GlobalTransform)>)
+{
+    let camera = cameras.iter().find(|(c, _)| c.is_active).map(|(_, t)| t.translation());
+
+    for (entity, mut ldtk, global) in query.iter_mut() {
+        if !asset_server.is_loaded_with_dependencies(&ldtk.project) {
+            continue;
+        }
+        let Some(project) = projects.get(&ldtk.project) else { continue };
+
+        // the camera's position within the plane, in pixels.
+        let (right, down, _) = ldtk.plane.axes();
+        let focus = camera.map(|c| {
+            let local = global.affine().inverse().transform_point3(c);
+            Vec2::new(local.dot(right), local.dot(down)) * ldtk.pixels_per_metre
+        });
+
+        for (index, level) in project.levels.iter().enumerate() {
+            if ldtk.spawned.contains(&index) {
+                continue;
+            }
+            let min = level.world_position.as_vec2();
+            let max = min + level.size.as_vec2();
+            let near = focus.is_some_and(|f| {
+                let outside = (min - f).max(f - max).max(Vec2::ZERO);
+                outside.length() / ldtk.pixels_per_metre <= ldtk.spawn_distance
+            });
+            if !near {
+                continue;
+            }
+            ldtk.spawned.insert(index);
+
+            let root = commands.spawn((Name::new(level.identifier.clone()),
+                                       Sprite3dLdtkLevel { index, iid: level.iid.clone() },
+                                       Transform::from_translation(ldtk.to_local(min, 0.)),
+                                       Visibility::default(),
+                                       ChildOf(entity)))
+                               .id();
+
+            for (i, layer) in level.layers.iter().enumerate() {
+                if !layer.visible {
+                    continue;
+                }
+                let height = i as f32 * ldtk.layer_spacing;
+                let origin = ldtk.to_local(layer.offset, height);
+
+                if let Some(tileset) = layer.tileset.map(|t| &project.tilesets[t]) {
+                    if ldtk.merge {
+                        spawn_merged_tiles(&mut commands, root, layer, tileset, &ldtk, origin);
+                    } else {
+                        spawn_tile_sprites(&mut commands, root, layer, tileset, &ldtk, origin);
+                    }
+                }
+
+                if layer.kind == LdtkLayerKind::IntGrid && !ldtk.walls.is_empty() {
+                    let tileset = layer.tileset.map(|t| &project.tilesets[t]);
+                    let image = tileset.and_then(|t| images.get(&t.image));
+                    let layout = tileset.and_then(|t| atlas_layouts.get(&t.layout));
+                    if let (Some(tileset), Some(image), Some(layout)) = (tileset, image, layout) {
+                        if let Some(mesh) = wall_mesh(layer, &ldtk, layout, image.size().as_vec2()) {
+                            let material = caches.material(&mut materials,
+                                                           &tileset.image,
+                                                           DEFAULT_ALPHA_MODE,
+                                                           false,
+                                                           LinearRgba::BLACK);
+                            commands.spawn((Name::new(format!("{} walls", layer.identifier)),
+                                            Mesh3d(meshes.add(mesh)),
+                                            material,
+                                            Transform::from_translation(origin),
+                                            ChildOf(root)));
+                        }
+                    }
+                }
+
+                for e in &layer.entities {
+                    spawn_entity(&mut commands, root, project, e, &ldtk, origin);
+                }
+            }
+        }
+    }
+}
+
+// a tilemap per tile stacked in the same cell, since each tilemap cell holds
+// one tile.
+fn spawn_merged_tiles(commands: &mut Commands,
+                      parent: Entity,
+                      layer: &LdtkLayer,
+                      tileset: &LdtkTileset,
+                      ldtk: &Sprite3dLdtk,
+                      origin: Vec3)
+{
+    let grid = layer.grid_size.max(1) as i32;
+    let tile_world = Vec2::splat(layer.grid_size as f32 / ldtk.pixels_per_metre);
+    let mut stacks: Vec<Sprite3dTilemap> = Vec::new();
+
+    for tile in &layer.tiles {
+        let cell = tile.position.div_euclid(IVec2::splat(grid));
+        if cell.min_element() < 0 {
+            continue;
+        }
+        let cell = cell.as_uvec2();
+        let depth = stacks.iter().position(|map| map.tile(cell).is_none()).unwrap_or(stacks.len());
+        if depth == stacks.len() {
+            stacks.push(Sprite3dTilemap::new(tileset.image.clone(), tileset.layout.clone(), layer.size, tile_world)
+                .with_plane(ldtk.plane));
+        }
+        stacks[depth].set_tile(cell, tile.index);
+    }
+
+    // nudge stacked tilemaps apart so they don't z-fight.
+    let (_, _, normal) = ldtk.plane.axes();
+    for (depth, map) in stacks.into_iter().enumerate() {
+        let offset = normal * depth as f32 * ldtk.layer_spacing / 4.;
+        commands.spawn((Name::new(layer.identifier.clone()),
+                        map,
+                        Transform::from_translation(origin + offset),
+                        ChildOf(parent)));
+    }
+}
+
+fn spawn_tile_sprites(commands: &mut Commands,
+                      parent: Entity,
+                      layer: &LdtkLayer,
+                      tileset: &LdtkTileset,
+                      ldtk: &Sprite3dLdtk,
+                      origin: Vec3)
+{
+    let grid = layer.grid_size as f32;
+    for tile in &layer.tiles {
+        let corner = tile.position.as_vec2() + Vec2::new(0., grid);
+        let sprite = Sprite { image: tileset.image.clone(),
+                              texture_atlas: Some(TextureAtlas { layout: tileset.layout.clone(), index: tile.index }),
+                              flip_x: tile.flip_x,
+                              flip_y: tile.flip_y,
+                              ..default() };
+        commands.spawn((sprite,
+                        Sprite3d { pixels_per_metre: ldtk.pixels_per_metre, pivot: Some(Vec2::ZERO), ..default() },
+                        Transform::from_translation(origin + ldtk.to_local(corner, 0.)).with_rotation(ldtk.flat()),
+                        ChildOf(parent)));
+    }
+}
+
+fn spawn_entity(commands: &mut Commands,
+                parent: Entity,
+                project: &Sprite3dLdtkProject,
+                e: &LdtkEntity,
+                ldtk: &Sprite3dLdtk,
+                origin: Vec3)
+{
+    let transform = Transform::from_translation(origin + ldtk.to_local(e.position, 0.));
+    let mut entity = commands.spawn((Name::new(e.identifier.clone()), e.clone(), transform, ChildOf(parent)));
+
+    let Some((tileset, index)) = e.tile else { return };
+    let tileset = &project.tilesets[tileset];
+    let sprite = Sprite { image: tileset.image.clone(),
+                          texture_atlas: Some(TextureAtlas { layout: tileset.layout.clone(), index }),
+                          ..default() };
+    // ldtk's pivot is from the top-left, Sprite3d's from the bottom-left.
+    entity.insert((sprite,
+                   Sprite3d { pixels_per_metre: ldtk.pixels_per_metre,
+                              pivot: Some(Vec2::new(e.pivot.x, 1. - e.pivot.y)),
+                              ..default() }));
+}
+
+// The box mesh for every wall cell in an IntGrid layer. Faces between two
+// wall cells are left out.
+fn wall_mesh(layer: &LdtkLayer, ldtk: &Sprite3dLdtk, layout: &TextureAtlasLayout, image_size: Vec2) -> Option<Mesh>
+{
+    let (right, down, normal) = ldtk.plane.axes();
+    let grid = layer.grid_size as f32 / ldtk.pixels_per_metre;
+
+    // the auto-layer tile drawn in each cell.
+    let grid_px = layer.grid_size.max(1) as i32;
+    let mut cell_tiles = HashMap::new();
+    for tile in &layer.tiles {
+        cell_tiles.entry(tile.position.div_euclid(IVec2::splat(grid_px))).or_insert(tile.index);
+    }
+
+    let mut mesh = QuadMesh::default();
+    for y in 0..layer.size.y as i32 {
+        for x in 0..layer.size.x as i32 {
+            let cell = IVec2::new(x, y);
+            let Some(wall) = ldtk.walls.get(&layer.int_value(cell)) else { continue };
+            let Some(index) = cell_tiles.get(&cell).copied().or(wall.tile) else { continue };
+            let Some(&rect) = layout.textures.get(index) else { continue };
+            let cell_uvs = tile_uvs(rect, image_size);
+
+            let is_wall = |dx: i32, dy: i32| ldtk.walls.contains_key(&layer.int_value(cell + IVec2::new(dx, dy)));
+            let h = wall.height;
+            let base = right * (x as f32 + 0.5) * grid + down * (y as f32 + 0.5) * grid;
+
+            // (centre, outward normal, up, half width, half height)
+            let mut faces = vec![(base + normal * h, normal, -down, grid / 2., grid / 2.)];
+            for (dx, dy, out) in [(0, -1, -down), (0, 1, down), (-1, 0, -right), (1, 0, right)] {
+                if !is_wall(dx, dy) {
+                    faces.push((base + out * grid / 2. + normal * h / 2., out, normal, grid / 2., h / 2.));
+                }
+            }
+
+            for (centre, out, up, hw, hh) in faces {
+                mesh.push(centre, out, up, Vec2::new(hw, hh), cell_uvs);
+            }
+        }
+    }
+    mesh.build()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // a one level project, trimmed down to the fields the loader reads.
+    const PROJECT: &str = r#"{
+        "defs": { "tilesets": [{ "uid": 7, "identifier": "Dungeon", "relPath": "dungeon.png", "tileGridSize": 16,
+                                 "spacing": 0, "padding": 0, "__cWid": 4, "__cHei": 4, "pxWid": 64, "pxHei": 64 }] },
+        "levels": [{
+            "identifier": "Entrance", "iid": "level-1", "worldX": 256, "worldY": -128, "pxWid": 32, "pxHei": 16,
+            "fieldInstances": [{ "__identifier": "dark", "__value": true }],
+            "layerInstances": [
+                { "__identifier": "Things", "__type": "Entities", "__cWid": 2, "__cHei": 1, "__gridSize": 16,
+                  "__tilesetDefUid": null,
+                  "entityInstances": [{ "__identifier": "Chest", "iid": "chest-1", "__tags": ["loot"],
+                                        "__pivot": [0.5, 1], "__tile": { "tilesetUid": 7, "x": 16, "y": 0, "w": 16, "h": 32 },
+                                        "width": 16, "height": 32, "px": [24, 16],
+                                        "fieldInstances": [{ "__identifier": "gold", "__value": 30 }] }] },
+                { "__identifier": "Floor", "__type": "Tiles", "__cWid": 2, "__cHei": 1, "__gridSize": 16,
+                  "__pxTotalOffsetX": 8, "__tilesetDefUid": 7, "visible": false,
+                  "gridTiles": [{ "px": [0, 0], "f": 0, "t": 5 }, { "px": [16, 0], "f": 3, "t": 6 }] },
+                { "__identifier": "Walls", "__type": "IntGrid", "__cWid": 2, "__cHei": 1, "__gridSize": 16,
+                  "__tilesetDefUid": 7, "intGridCsv": [1, 0],
+                  "autoLayerTiles": [{ "px": [0, 0], "t": 9 }] }
+            ]
+        }]
+    }"#;
+
+    fn level() -> (LdtkLevel, Vec<TextureAtlasLayout>)
+    {
+        let mut project: JsonProject = serde_json::from_str(PROJECT).unwrap();
+        let mut layouts = vec![TextureAtlasLayout::from_grid(UVec2::splat(16), 4, 4, None, None)];
+        let level = parse_level(project.levels.remove(0), &project.defs.tilesets, &mut layouts);
+        (level, layouts)
+    }
+
+    #[test]
+    fn level_and_layers()
+    {
+        let (level, _) = level();
+        assert_eq!(level.identifier, "Entrance");
+        assert_eq!(level.world_position, IVec2::new(256, -128));
+        assert_eq!(level.size, UVec2::new(32, 16));
+        assert_eq!(level.fields["dark"], serde_json::Value::Bool(true));
+
+        // bottom to top, the reverse of the file.
+        let kinds: Vec<LdtkLayerKind> = level.layers.iter().map(|l| l.kind).collect();
+        assert_eq!(kinds, [LdtkLayerKind::IntGrid, LdtkLayerKind::Tiles, LdtkLayerKind::Entities]);
+        assert_eq!(level.layers[0].int_grid, [1, 0]);
+        assert_eq!(level.layers[0].tiles[0].index, 9);
+    }
+
+    #[test]
+    fn tiles()
+    {
+        let (level, _) = level();
+        let floor = &level.layers[1];
+        assert_eq!(floor.tileset, Some(0));
+        assert_eq!(floor.offset, Vec2::new(8., 0.));
+        assert!(!floor.visible);
+        assert_eq!(floor.tiles.len(), 2);
+        assert_eq!((floor.tiles[0].index, floor.tiles[0].flip_x, floor.tiles[0].flip_y), (5, false, false));
+        assert_eq!((floor.tiles[1].position, floor.tiles[1].flip_x, floor.tiles[1].flip_y), (IVec2::new(16, 0), true, true));
+    }
+
+    #[test]
+    fn entities()
+    {
+        let (level, layouts) = level();
+        let chest = &level.layers[2].entities[0];
+        assert_eq!(chest.identifier, "Chest");
+        assert_eq!(chest.tags, ["loot"]);
+        assert_eq!(chest.position, Vec2::new(24., 16.));
+        assert_eq!(chest.pivot, Vec2::new(0.5, 1.));
+        assert_eq!(chest.fields["gold"], serde_json::json!(30));
+
+        // the chest's tile is two cells tall, so it's added to the layout.
+        assert_eq!(layouts[0].textures.len(), 17);
+        assert_eq!(chest.tile, Some((0, 16)));
+        assert_eq!(layouts[0].textures[16], URect::new(16, 0, 32, 32));
+    }
+}
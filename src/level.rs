@@ -0,0 +1,354 @@
+use bevy::asset::RenderAssetUsages;
+use bevy::mesh::{Indices, PrimitiveTopology};
+use bevy::prelude::*;
+
+use crate::tilemap::tile_uvs;
+use crate::{Sprite3d, Sprite3dCaches, Sprite3dTilemap, TilemapPlane, DEFAULT_ALPHA_MODE};
+
+/// Which atlas tiles a [`Sprite3dLevel`] builds its floors and walls from.
+///
+/// Where there's a choice of variants, one is picked per tile from the
+/// level's `seed`, so the same level always looks the same. List a variant
+/// more than once to make it more common.
+#[derive(Clone, Debug, Default)]
+pub struct LevelTiles
+{
+    /// Floor tile variants.
+    pub floor:          Vec<usize>,
+
+    /// Wall variants, each a column of atlas indices from the bottom of the
+    /// wall up. Columns shorter than `wall_height` repeat their top tile.
+    pub wall:           Vec<Vec<usize>>,
+
+    /// The column to use where a wall ends to its left (seen from the floor),
+    /// ie. at a corner.
+    pub wall_left_end:  Option<Vec<usize>>,
+
+    /// As `wall_left_end`, for the right. Also used for walls only one tile
+    /// wide, which end on both sides.
+    pub wall_right_end: Option<Vec<usize>>,
+
+    /// How many tiles tall walls are.
+    pub wall_height:    u32,
+}
+
+/// Builds a 2.5d level from an occupancy grid: a floor wherever the grid is
+/// filled, and walls standing along every edge between floor and empty space,
+/// facing the floor.
+///
+/// Like [`Sprite3dTilemap`], cell `(0, 0)` has its top-left corner at the
+/// level's origin, and everything is spawned as children once the image and
+/// layout have loaded. With `merge` on (default) the floor becomes a
+/// `Sprite3dTilemap` and the walls a single mesh, otherwise every tile is its
+/// own `Sprite3d`.
+#[derive(Component, Clone, Debug)]
+#[require(Transform, Visibility)]
+pub struct Sprite3dLevel
+{
+    pub image:      Handle<Image>,
+    pub layout:     Handle<TextureAtlasLayout>,
+
+    /// The size of the grid, in cells.
+    pub size:       UVec2,
+
+    /// The world-space size of a single tile. Walls are `tile_size.y` tall
+    /// per tile.
+    pub tile_size:  Vec2,
+    pub plane:      TilemapPlane,
+    pub tiles:      LevelTiles,
+    pub merge:      bool,
+
+    /// Picks between tile variants.
+    pub seed:       u64,
+
+    /// How far walls are moved in from the edge of the floor, so that they
+    /// don't z-fight with anything on the far side of it.
+    pub wall_inset: f32,
+
+    floor:          Vec<bool>,
+    // explicitly placed floor tiles, overriding `tiles.floor`.
+    floor_tiles:    Vec<Option<usize>>,
+    built:          bool,
+}
+
+impl Sprite3dLevel
+{
+    /// An empty level of `size` cells.
+    pub fn new(image: Handle<Image>,
+               layout: Handle<TextureAtlasLayout>,
+               size: UVec2,
+               tile_size: Vec2,
+               tiles: LevelTiles)
+               -> Self
+    {
+        let cells = (size.x * size.y) as usize;
+        Self { image,
+               layout,
+               size,
+               tile_size,
+               plane: TilemapPlane::XZ,
+               tiles,
+               merge: true,
+               seed: 0,
+               wall_inset: 0.001,
+               floor: vec![false; cells],
+               floor_tiles: vec![None; cells],
+               built: false }
+    }
+
+    /// Fills every cell for which `filled` returns `true` with floor.
+    pub fn with_floor(mut self, mut filled: impl FnMut(UVec2) -> bool) -> Self
+    {
+        for i in 0..self.floor.len() {
+            self.floor[i] = filled(self.cell(i));
+        }
+        self
+    }
+
+    /// Fills cells with specific floor tiles, rather than picking from
+    /// `LevelTiles::floor`. `None` leaves the cell empty.
+    pub fn with_floor_tiles(mut self, mut tile: impl FnMut(UVec2) -> Option<usize>) -> Self
+    {
+        for i in 0..self.floor.len() {
+            self.floor_tiles[i] = tile(self.cell(i));
+            self.floor[i] = self.floor_tiles[i].is_some();
+        }
+        self
+    }
+
+    pub fn with_plane(mut self, plane: TilemapPlane) -> Self
+    {
+        self.plane = plane;
+        self
+    }
+
+    pub fn with_merge(mut self, merge: bool) -> Self
+    {
+        self.merge = merge;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self
+    {
+        self.seed = seed;
+        self
+    }
+
+    /// Whether `pos` has floor. Cells outside the grid don't.
+    pub fn is_floor(&self, pos: IVec2) -> bool
+    {
+        pos.cmpge(IVec2::ZERO).all()
+            && pos.cmplt(self.size.as_ivec2()).all()
+            && self.floor[(pos.y * self.size.x as i32 + pos.x) as usize]
+    }
+
+    fn cell(&self, i: usize) -> UVec2 { UVec2::new(i as u32 % self.size.x, i as u32 / self.size.x) }
+
+    fn floor_tile(&self, pos: UVec2) -> Option<usize>
+    {
+        let i = (pos.y * self.size.x + pos.x) as usize;
+        if !self.floor[i] {
+            return None;
+        }
+        self.floor_tiles[i].or_else(|| pick(&self.tiles.floor, cell_hash(self.seed, pos.as_ivec2(), 0)).copied())
+    }
+
+    // every wall in the level, as (floor cell, direction to the empty cell,
+    // column of atlas indices).
+    fn walls(&self) -> Vec<(IVec2, IVec2, Vec<usize>)>
+    {
+        let (right_axis, down_axis, normal) = self.plane.axes();
+        let mut walls = Vec::new();
+
+        for y in 0..self.size.y as i32 {
+            for x in 0..self.size.x as i32 {
+                let cell = IVec2::new(x, y);
+                if !self.is_floor(cell) {
+                    continue;
+                }
+                for (salt, dir) in [IVec2::NEG_Y, IVec2::Y, IVec2::NEG_X, IVec2::X].into_iter().enumerate() {
+                    if self.is_floor(cell + dir) {
+                        continue;
+                    }
+                    // the cell to the right, seen from the floor facing the wall.
+                    let forward = right_axis * dir.x as f32 + down_axis * dir.y as f32;
+                    let side = forward.cross(normal);
+                    let right = IVec2::new(side.dot(right_axis).round() as i32, side.dot(down_axis).round() as i32);
+
+                    // a wall carries on to a side if there's floor there, and
+                    // it isn't turning a corner.
+                    let continues = |side: IVec2| self.is_floor(cell + side) && !self.is_floor(cell + side + dir);
+                    let column = match (continues(-right), continues(right)) {
+                        (_, false) => self.tiles.wall_right_end.as_ref(),
+                        (false, true) => self.tiles.wall_left_end.as_ref(),
+                        _ => None,
+                    };
+                    let column = column.or_else(|| pick(&self.tiles.wall, cell_hash(self.seed, cell, 1 + salt as u64)));
+                    if let Some(column) = column {
+                        walls.push((cell, dir, column.clone()));
+                    }
+                }
+            }
+        }
+        walls
+    }
+
+    // the centre of the edge of `cell` in direction `dir`, pulled in by the
+    // inset, and the direction the wall faces.
+    fn wall_base(&self, cell: IVec2, dir: IVec2) -> (Vec3, Vec3)
+    {
+        let (right, down, _) = self.plane.axes();
+        let inward = -(right * dir.x as f32 + down * dir.y as f32);
+        let centre = (cell.as_vec2() + 0.5 + dir.as_vec2() * 0.5) * self.tile_size;
+        (right * centre.x + down * centre.y + inward * self.wall_inset, inward)
+    }
+
+    // width of a wall along the edge it stands on.
+    fn wall_width(&self, dir: IVec2) -> f32 { if dir.x == 0 { self.tile_size.x } else { self.tile_size.y } }
+
+    fn wall_row(&self, column: &[usize], row: u32) -> Option<usize>
+    {
+        column.get(row as usize).or(column.last()).copied()
+    }
+}
+
+fn pick<T>(variants: &[T], hash: u64) -> Option<&T>
+{
+    (!variants.is_empty()).then(|| &variants[(hash % variants.len() as u64) as usize])
+}
+
+// a cheap, stable hash of a cell (splitmix64).
+pub(crate) fn cell_hash(seed: u64, pos: IVec2, salt: u64) -> u64
+{
+    let mut z = seed ^ ((pos.x as u32 as u64) << 32 | pos.y as u32 as u64) ^ salt.wrapping_mul(0xa076_1d64_78bd_642f);
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+
+// Accumulates textured quads into a single mesh.
+#[derive(Default)]
+pub(crate) struct QuadMesh
+{
+    positions: Vec<[f32; 3]>,
+    normals:   Vec<[f32; 3]>,
+    uvs:       Vec<[f32; 2]>,
+    indices:   Vec<u32>,
+}
+
+impl QuadMesh
+{
+    // adds a quad facing `out`, with `up` towards the top of its image.
+    pub fn push(&mut self, centre: Vec3, out: Vec3, up: Vec3, half_size: Vec2, uvs: [[f32; 2]; 4])
+    {
+        let side = (-out).cross(up) * half_size.x;
+        let up = up * half_size.y;
+        let first = self.positions.len() as u32;
+        // bottom-left, bottom-right, top-right, top-left, seen from the front.
+        self.positions.extend([(centre - side - up).to_array(),
+                               (centre + side - up).to_array(),
+                               (centre + side + up).to_array(),
+                               (centre - side + up).to_array()]);
+        self.normals.extend([out.to_array(); 4]);
+        self.uvs.extend(uvs);
+        self.indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+
+    pub fn build(self) -> Option<Mesh>
+    {
+        if self.positions.is_empty() {
+            return None;
+        }
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default());
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        mesh.insert_indices(Indices::U32(self.indices));
+        Some(mesh)
+    }
+}
+
+
+// Spawn the floors and walls of new levels, once their tileset has loaded.
+pub(crate) fn build_levels(mut commands: Commands,
+                           images: Res<Assets<Image>>,
+                           atlas_layouts: Res<Assets<TextureAtlasLayout>>,
+                           mut caches: ResMut<Sprite3dCaches>,
+                           mut meshes: ResMut<Assets<Mesh>>,
+                           mut materials: ResMut<Assets<StandardMaterial>>,
+                           mut query: Query<(Entity, &mut Sprite3dLevel)>)
+{
+    for (entity, mut level) in query.iter_mut() {
+        if level.built {
+            continue;
+        }
+        let (Some(image), Some(layout)) = (images.get(&level.image), atlas_layouts.get(&level.layout)) else {
+            continue;
+        };
+        level.built = true;
+        let (_, _, normal) = level.plane.axes();
+        let walls = level.walls();
+
+        if level.merge {
+            let floor = Sprite3dTilemap::new(level.image.clone(), level.layout.clone(), level.size, level.tile_size)
+                .with_plane(level.plane)
+                .with_tiles(|pos| level.floor_tile(pos));
+            commands.spawn((Name::new("floor"), floor, ChildOf(entity)));
+
+            let image_size = image.size().as_vec2();
+            let mut mesh = QuadMesh::default();
+            for (cell, dir, column) in &walls {
+                let (base, inward) = level.wall_base(*cell, *dir);
+                let half_size = Vec2::new(level.wall_width(*dir), level.tile_size.y) / 2.;
+                for row in 0..level.tiles.wall_height {
+                    let Some(rect) = level.wall_row(column, row).and_then(|i| layout.textures.get(i)) else {
+                        continue;
+                    };
+                    let centre = base + normal * (row as f32 + 0.5) * level.tile_size.y;
+                    mesh.push(centre, inward, normal, half_size, tile_uvs(*rect, image_size));
+                }
+            }
+            if let Some(mesh) = mesh.build() {
+                let material = caches.material(&mut materials, &level.image, DEFAULT_ALPHA_MODE, false, LinearRgba::BLACK);
+                commands.spawn((Name::new("walls"), Mesh3d(meshes.add(mesh)), material, ChildOf(entity)));
+            }
+            continue;
+        }
+
+        // a sprite per tile. Sprites face +Z, so the floor's are laid flat.
+        let pixels_per_metre = |rect: URect| rect.width() as f32 / level.tile_size.x;
+        let flat = match level.plane {
+            TilemapPlane::XZ => Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2),
+            TilemapPlane::XY => Quat::IDENTITY,
+        };
+        let (right, down, _) = level.plane.axes();
+        let sprite = |index: usize| Sprite { image: level.image.clone(),
+                                             texture_atlas: Some(TextureAtlas { layout: level.layout.clone(), index }),
+                                             ..default() };
+
+        for i in 0..level.floor.len() {
+            let pos = level.cell(i);
+            let Some(index) = level.floor_tile(pos) else { continue };
+            let Some(&rect) = layout.textures.get(index) else { continue };
+            let centre = (pos.as_vec2() + 0.5) * level.tile_size;
+            commands.spawn((sprite(index),
+                            Sprite3d { pixels_per_metre: pixels_per_metre(rect), ..default() },
+                            Transform::from_translation(right * centre.x + down * centre.y).with_rotation(flat),
+                            ChildOf(entity)));
+        }
+        for (cell, dir, column) in &walls {
+            let (base, inward) = level.wall_base(*cell, *dir);
+            for row in 0..level.tiles.wall_height {
+                let Some(index) = level.wall_row(column, row) else { continue };
+                let Some(&rect) = layout.textures.get(index) else { continue };
+                let centre = base + normal * (row as f32 + 0.5) * level.tile_size.y;
+                commands.spawn((sprite(index),
+                                Sprite3d { pixels_per_metre: pixels_per_metre(rect), ..default() },
+                                Transform::from_translation(centre).looking_to(-inward, normal),
+                                ChildOf(entity)));
+            }
+        }
+    }
+}
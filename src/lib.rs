@@ -5,18 +5,94 @@ use bevy::prelude::*;
 use bevy::render::render_resource::*;
 use std::hash::Hash;
 
+use crate::extrude::extruded_mesh;
+use crate::tight::tight_mesh;
+
+mod animation;
+#[cfg(feature = "aseprite")]
+mod aseprite;
+mod atlas;
+mod autotile;
+mod directional;
+mod extrude;
+mod flipbook;
+mod grid;
+mod instanced;
+#[cfg(feature = "ldtk")]
+mod ldtk;
+mod level;
+mod mipmap;
+mod padding;
+mod particles;
+#[cfg(feature = "picking")]
+mod picking;
 pub mod prelude;
+mod raycast;
+#[cfg(any(feature = "aseprite", feature = "texture_packer"))]
+mod sheet;
+#[cfg(feature = "texture_packer")]
+mod texture_packer;
+mod tight;
+#[cfg(feature = "tiled")]
+mod tiled;
+mod tilemap;
+
+pub use animation::*;
+#[cfg(feature = "aseprite")]
+pub use aseprite::*;
+pub use atlas::*;
+pub use autotile::*;
+pub use directional::*;
+pub use flipbook::*;
+pub use grid::*;
+pub use instanced::Sprite3dInstanced;
+#[cfg(feature = "ldtk")]
+pub use ldtk::*;
+pub use level::*;
+pub use mipmap::*;
+pub use padding::*;
+pub use particles::*;
+#[cfg(feature = "picking")]
+pub use picking::*;
+pub use raycast::*;
+#[cfg(any(feature = "aseprite", feature = "texture_packer"))]
+pub use sheet::Sprite3dAtlasLoaderError;
+#[cfg(feature = "texture_packer")]
+pub use texture_packer::*;
+#[cfg(feature = "tiled")]
+pub use tiled::*;
+pub use tilemap::*;
 
 pub struct Sprite3dPlugin;
 #[rustfmt::skip]
 impl Plugin for Sprite3dPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<Sprite3dCaches>();
+        app.add_plugins((instanced::InstancedSpritePlugin, flipbook::FlipbookPlugin));
+        app.init_asset::<Sprite3dAtlas>()
+           .init_asset::<Sprite3dFrames>();
+        #[cfg(feature = "aseprite")]
+        app.register_asset_loader(AsepriteLoader);
+        #[cfg(feature = "texture_packer")]
+        app.register_asset_loader(TexturePackerLoader);
+        #[cfg(feature = "tiled")]
+        app.init_asset::<Sprite3dTiledMap>()
+           .register_asset_loader(TiledLoader)
+           .add_systems(PostUpdate, spawn_tiled_maps.before(build_levels));
+        #[cfg(feature = "ldtk")]
+        app.init_asset::<Sprite3dLdtkProject>()
+           .register_asset_loader(LdtkLoader)
+           .add_systems(PostUpdate, spawn_ldtk_levels.before(build_levels));
         app.add_systems(
             PostUpdate,
-            (bundle_builder, (
+            ((animate_sprites, update_directional_sprites, update_emitters).chain()
+                                                                           .before(bundle_builder)
+                                                                           .before(TransformSystems::Propagate),
+             bundle_builder, (
                 handle_texture_atlases, handle_images
-            ).after(bundle_builder))
+            ).after(bundle_builder),
+             apply_flipbooks.after(bundle_builder).before(handle_texture_atlases),
+             (build_levels, resolve_autotiles, build_tilemaps, animate_tilemaps).chain())
         );
     }
 }
@@ -25,10 +101,21 @@ impl Plugin for Sprite3dPlugin {
 // sizes are multiplied by this, then cast to ints to query the mesh hashmap.
 const MESH_CACHE_GRANULARITY: f32 = 1000.;
 
+/// Identifies a cached sprite mesh by its size, pivot, UV rect and options.
+/// Meshes built from an image's pixels, like extruded or tight sprites, are
+/// also keyed by the image.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct MeshKey
+{
+    dims:  [u32; 12],
+    image: Option<AssetId<Image>>,
+}
+
 #[derive(Eq, Hash, PartialEq)]
 pub struct MatKey
 {
     image:      Handle<Image>,
+    colour:     [u8; 4],
     alpha_mode: HashableAlphaMode,
     unlit:      bool,
     emissive:   [u8; 4],
@@ -73,34 +160,71 @@ fn reduce_colour(c: LinearRgba) -> [u8; 4] { [
 #[derive(Resource, Default)]
 pub struct Sprite3dCaches
 {
-    pub mesh_cache:     HashMap<[u32; 9], Mesh3d>,
+    pub mesh_cache:     HashMap<MeshKey, Mesh3d>,
     pub material_cache: HashMap<MatKey, MeshMaterial3d<StandardMaterial>>,
 }
 
+impl Sprite3dCaches
+{
+    // fetch the material for an unflipped image, creating and caching it if
+    // need be. Used by things built from many sprites, like tilemaps.
+    pub(crate) fn material(&mut self,
+                           materials: &mut Assets<StandardMaterial>,
+                           image: &Handle<Image>,
+                           alpha_mode: AlphaMode,
+                           unlit: bool,
+                           emissive: LinearRgba)
+                           -> MeshMaterial3d<StandardMaterial>
+    {
+        let mat_key = MatKey { image:      image.clone(),
+                               colour:     reduce_colour(LinearRgba::WHITE),
+                               alpha_mode: HashableAlphaMode(alpha_mode),
+                               unlit,
+                               emissive:   reduce_colour(emissive),
+                               flip_x:     false,
+                               flip_y:     false, };
+        self.material_cache.entry(mat_key).or_insert_with(|| {
+            MeshMaterial3d(materials.add(build_material(image.clone(), Color::WHITE, alpha_mode, unlit, emissive, false, false)))
+        }).clone()
+    }
+}
+
 #[rustfmt::skip]
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 fn bundle_builder(mut commands: Commands,
                   images: Res<Assets<Image>>,
                   mut caches: ResMut<Sprite3dCaches>,
                   mut meshes: ResMut<Assets<Mesh>>,
                   mut materials: ResMut<Assets<StandardMaterial>>,
                   atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+                  sprite_frames: Res<Assets<Sprite3dFrames>>,
                   mut query: Query<(&mut Sprite3d,
                          &mut Mesh3d,
                          &mut MeshMaterial3d<StandardMaterial>,
                          &Sprite,
                          Entity),
-                        With<Sprite3dBuilder>>)
+                        (With<Sprite3dBuilder>, Without<Sprite3dInstanced>)>)
 {
     for (mut sprite3d, mut mesh, mut mat, sprite, e) in query.iter_mut() {
         // get image dimensions
-        let image_size = images.get(&sprite.image).unwrap().texture_descriptor.size;
+        let image = images.get(&sprite.image).unwrap();
+        let image_size = image.texture_descriptor.size;
         // w & h are the world-space size of the sprite.
         let w = (image_size.width as f32) / sprite3d.pixels_per_metre;
         let h = (image_size.height as f32) / sprite3d.pixels_per_metre;
         let pivot = sprite3d.pivot.unwrap_or(Vec2::new(0.5, 0.5));
+        // extruded and tight meshes depend on the image's pixels, so can't be
+        // shared between images.
+        let extrude = (sprite3d.extrude_depth.max(0.) * MESH_CACHE_GRANULARITY) as u32;
+        let tight = sprite3d.tight_mesh.map_or(0, |max| max.max(3) as u32);
+        let image_id = (extrude > 0 || tight > 0).then(|| sprite.image.id());
+        // the inset only applies to quads.
+        let inset_texels = if image_id.is_some() { 0. } else { sprite3d.uv_inset.max(0.) };
+        let uv_inset = (inset_texels * MESH_CACHE_GRANULARITY) as u32;
 
         if let Some(atlas) = &sprite.texture_atlas {
             let atlas_layout = atlas_layouts.get(&atlas.layout).unwrap();
+            let frames = sprite3d.frames.as_ref().and_then(|f| sprite_frames.get(f));
 
             // cache all the meshes for the atlas (if they haven't been already)
             // so that we can change the index later and not have to re-create the mesh.
@@ -119,6 +243,16 @@ fn bundle_builder(mut commands: Commands,
                                    rect.max.y as f32 / (image_size.height as f32)),
                 };
 
+                // frames can bring their own pivot, overriding the sprite's.
+                let pivot = frames.and_then(|f| f.pivot(i)).unwrap_or(pivot);
+
+                // a trimmed frame's pivot is relative to the untrimmed frame, so
+                // shift it to be relative to what's actually left in the atlas.
+                let pivot = match frames.and_then(|f| f.trim(i)) {
+                    Some(trim) => trimmed_pivot(pivot, rect, trim),
+                    None => pivot,
+                };
+
                 let mut rect_pivot = pivot;
 
                 // scale pivot to be relative to the rect within the atlas.
@@ -127,42 +261,58 @@ fn bundle_builder(mut commands: Commands,
                 rect_pivot += frac_rect.min;
 
 
-                let mesh_key = [(w * MESH_CACHE_GRANULARITY) as u32,
-                                (h * MESH_CACHE_GRANULARITY) as u32,
-                                (rect_pivot.x * MESH_CACHE_GRANULARITY) as u32,
-                                (rect_pivot.y * MESH_CACHE_GRANULARITY) as u32,
-                                sprite3d.double_sided as u32,
-                                (frac_rect.min.x * MESH_CACHE_GRANULARITY) as u32,
-                                (frac_rect.min.y * MESH_CACHE_GRANULARITY) as u32,
-                                (frac_rect.max.x * MESH_CACHE_GRANULARITY) as u32,
-                                (frac_rect.max.y * MESH_CACHE_GRANULARITY) as u32];
+                let mesh_key = MeshKey { dims:  [(w * MESH_CACHE_GRANULARITY) as u32,
+                                                 (h * MESH_CACHE_GRANULARITY) as u32,
+                                                 (rect_pivot.x * MESH_CACHE_GRANULARITY) as i32 as u32,
+                                                 (rect_pivot.y * MESH_CACHE_GRANULARITY) as i32 as u32,
+                                                 sprite3d.double_sided as u32,
+                                                 (frac_rect.min.x * MESH_CACHE_GRANULARITY) as u32,
+                                                 (frac_rect.min.y * MESH_CACHE_GRANULARITY) as u32,
+                                                 (frac_rect.max.x * MESH_CACHE_GRANULARITY) as u32,
+                                                 (frac_rect.max.y * MESH_CACHE_GRANULARITY) as u32,
+                                                 extrude,
+                                                 tight,
+                                                 uv_inset],
+                                         image: image_id };
 
                 sprite3d.texture_atlas_keys.push(mesh_key);
 
                 // if we don't have a mesh in the cache, create it.
                 if !caches.mesh_cache.contains_key(&mesh_key) {
-                    let mut mesh = quad(w, h, Some(pivot), sprite3d.double_sided);
-                    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0,
-                                          vec![[frac_rect.min.x, frac_rect.max.y],
-                                               [frac_rect.max.x, frac_rect.max.y],
-                                               [frac_rect.min.x, frac_rect.min.y],
-                                               [frac_rect.max.x, frac_rect.min.y],
-                                               [frac_rect.min.x, frac_rect.max.y],
-                                               [frac_rect.max.x, frac_rect.max.y],
-                                               [frac_rect.min.x, frac_rect.min.y],
-                                               [frac_rect.max.x, frac_rect.min.y],]);
+                    let mesh = pixel_mesh(image, rect, Vec2::new(w, h), pivot, &sprite3d).unwrap_or_else(|| {
+                        // pull the UVs in from the frame's edges, so filtering
+                        // doesn't pick up its neighbours. Never past the centre.
+                        let texel = Vec2::new(image_size.width as f32, image_size.height as f32).recip();
+                        let inset = (texel * inset_texels).min(frac_rect.size() / 2.);
+                        let uv = bevy::math::Rect { min: frac_rect.min + inset, max: frac_rect.max - inset };
+                        let mut mesh = quad(w, h, Some(pivot), sprite3d.double_sided);
+                        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0,
+                                              vec![[uv.min.x, uv.max.y],
+                                                   [uv.max.x, uv.max.y],
+                                                   [uv.min.x, uv.min.y],
+                                                   [uv.max.x, uv.min.y],
+                                                   [uv.min.x, uv.max.y],
+                                                   [uv.max.x, uv.max.y],
+                                                   [uv.min.x, uv.min.y],
+                                                   [uv.max.x, uv.min.y],]);
+                        mesh
+                    });
                     let mesh_h = Mesh3d(meshes.add(mesh));
                     caches.mesh_cache.insert(mesh_key, mesh_h);
                 }
             }
         } else {
             // No texture atlas
-            let mesh_key = [(w * MESH_CACHE_GRANULARITY) as u32,
-                            (h * MESH_CACHE_GRANULARITY) as u32,
-                            (pivot.x * MESH_CACHE_GRANULARITY) as u32,
-                            (pivot.y * MESH_CACHE_GRANULARITY) as u32,
-                            sprite3d.double_sided as u32,
-                            0, 0, 0, 0];
+            let mesh_key = MeshKey { dims:  [(w * MESH_CACHE_GRANULARITY) as u32,
+                                             (h * MESH_CACHE_GRANULARITY) as u32,
+                                             (pivot.x * MESH_CACHE_GRANULARITY) as i32 as u32,
+                                             (pivot.y * MESH_CACHE_GRANULARITY) as i32 as u32,
+                                             sprite3d.double_sided as u32,
+                                             0, 0, 0, 0,
+                                             extrude,
+                                             tight,
+                                             0],
+                                     image: image_id };
             sprite3d.texture_atlas_keys.push(mesh_key);
         }
 
@@ -178,9 +328,10 @@ fn bundle_builder(mut commands: Commands,
                 mesh.clone()
             } else {
                 // otherwise, create a new mesh and cache it.
-                let mesh = Mesh3d(
-                    meshes.add(quad(w, h, sprite3d.pivot, sprite3d.double_sided))
-                );
+                let rect = URect::new(0, 0, image_size.width, image_size.height);
+                let mesh = pixel_mesh(image, rect, Vec2::new(w, h), pivot, &sprite3d)
+                    .unwrap_or_else(|| quad(w, h, sprite3d.pivot, sprite3d.double_sided));
+                let mesh = Mesh3d(meshes.add(mesh));
                 caches.mesh_cache.insert(mesh_key, mesh.clone());
                 mesh
             }
@@ -190,6 +341,7 @@ fn bundle_builder(mut commands: Commands,
         // (possibly look into a bool in Sprite3dBuilder to manually disable caching for an individual sprite?)
         *mat = {
             let mat_key = MatKey { image:      sprite.image.clone(),
+                                   colour:     reduce_colour(sprite.color.to_linear()),
                                    alpha_mode: HashableAlphaMode(sprite3d.alpha_mode),
                                    unlit:      sprite3d.unlit,
                                    emissive:   reduce_colour(sprite3d.emissive),
@@ -198,7 +350,7 @@ fn bundle_builder(mut commands: Commands,
             if let Some(material) = caches.material_cache.get(&mat_key) {
                 material.clone()
             } else {
-                let material = MeshMaterial3d(materials.add(build_material(sprite.image.clone(), sprite3d.alpha_mode, sprite3d.unlit, sprite3d.emissive, sprite.flip_x, sprite.flip_y)));
+                let material = MeshMaterial3d(materials.add(build_material(sprite.image.clone(), sprite.color, sprite3d.alpha_mode, sprite3d.unlit, sprite3d.emissive, sprite.flip_x, sprite.flip_y)));
                 caches.material_cache.insert(mat_key, material.clone());
                 material
             }
@@ -210,13 +362,15 @@ fn bundle_builder(mut commands: Commands,
 
 // Update the mesh when sprite image change
 #[rustfmt::skip]
+#[allow(clippy::type_complexity)]
 fn handle_images(
     mut caches: ResMut<Sprite3dCaches>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    mut query: Query<(&mut MeshMaterial3d<StandardMaterial>, &Sprite, &Sprite3d), Changed<Sprite>>)
+    mut query: Query<(&mut MeshMaterial3d<StandardMaterial>, &Sprite, &Sprite3d), (Changed<Sprite>, Without<Sprite3dInstanced>)>)
 {
     for (mut mesh_mat, sprite, sprite_3d) in query.iter_mut() {
         let mat_key = MatKey { image:      sprite.image.clone(),
+                               colour:     reduce_colour(sprite.color.to_linear()),
                                alpha_mode: HashableAlphaMode(sprite_3d.alpha_mode),
                                unlit:      sprite_3d.unlit,
                                emissive:   reduce_colour(sprite_3d.emissive),
@@ -230,6 +384,7 @@ fn handle_images(
                 materials.add(
                     build_material(
                         sprite.image.clone(),
+                        sprite.color,
                         sprite_3d.alpha_mode,
                         sprite_3d.unlit,
                         sprite_3d.emissive,
@@ -251,9 +406,10 @@ fn handle_images(
 
 // Update the mesh of a Sprite3d with an atlas sprite when its index changes.
 #[rustfmt::skip]
+#[allow(clippy::type_complexity)]
 fn handle_texture_atlases(
     caches: Res<Sprite3dCaches>,
-    mut query: Query<(&mut Mesh3d, &Sprite3d, &Sprite), Changed<Sprite>>)
+    mut query: Query<(&mut Mesh3d, &Sprite3d, &Sprite), (Changed<Sprite>, Without<Sprite3dInstanced>)>)
 {
     for (mut mesh, sprite_3d, sprite) in query.iter_mut() {
         let Some(texture_atlas) = &sprite.texture_atlas else {
@@ -271,6 +427,31 @@ fn handle_texture_atlases(
 
 
 
+// convert a pivot relative to an untrimmed frame into one relative to the
+// trimmed `rect` cut out of it. Both use a bottom-left origin.
+fn trimmed_pivot(pivot: Vec2, rect: URect, trim: FrameTrim) -> Vec2
+{
+    let source = trim.source_size.as_vec2();
+    let size = rect.size().as_vec2();
+    // bottom-left of the trimmed rect within the untrimmed frame, flipping y
+    // since trims are measured from the top.
+    let min = Vec2::new(trim.offset.x as f32, source.y - trim.offset.y as f32 - size.y);
+    (pivot * source - min) / size
+}
+
+
+// build the mesh for `rect` of a sprite's image from its pixels, if it's
+// extruded or tight. `None` for a plain quad, or if the pixels can't be read.
+fn pixel_mesh(image: &Image, rect: URect, size: Vec2, pivot: Vec2, sprite3d: &Sprite3d) -> Option<Mesh>
+{
+    if sprite3d.extrude_depth > 0. {
+        extruded_mesh(image, rect, size, pivot, sprite3d.extrude_depth)
+    } else {
+        sprite3d.tight_mesh.and_then(|max| tight_mesh(image, rect, size, pivot, max, sprite3d.double_sided))
+    }
+}
+
+
 // creates a (potentially offset) quad mesh facing +z
 // pivot = None will have a center pivot
 // pivot = Some(p) will have an expected range of p \in (0,0) to (1,1)
@@ -328,6 +509,7 @@ fn quad(w: f32, h: f32, pivot: Option<Vec2>, double_sided: bool) -> Mesh
 
 // generate a StandardMaterial useful for rendering a sprite
 fn build_material(image: Handle<Image>,
+                  colour: Color,
                   alpha_mode: AlphaMode,
                   unlit: bool,
                   emissive: LinearRgba,
@@ -335,7 +517,8 @@ fn build_material(image: Handle<Image>,
                   flip_y: bool)
                   -> StandardMaterial
 {
-    let mut mat = StandardMaterial { base_color_texture: Some(image),
+    let mut mat = StandardMaterial { base_color: colour,
+                                     base_color_texture: Some(image),
                                      cull_mode: Some(Face::Back),
                                      alpha_mode,
                                      unlit,
@@ -354,11 +537,15 @@ struct Sprite3dBuilder;
 /// Represents a 3D sprite. May store texture atlas data -- note that modifying
 /// `texture_atlas` and `texture_atlas_keys` on an already spawned sprite may
 /// cause buggy behavior.
+///
+/// The `Sprite`'s `color` tints the sprite. Each distinct tint gets its own
+/// cached material, so avoid animating it on many sprites (see
+/// [`Sprite3dInstanced`], which tints for free).
 #[derive(Component)]
 #[require(Transform, Mesh3d, MeshMaterial3d<StandardMaterial>, Sprite3dBuilder)]
 pub struct Sprite3d
 {
-    pub texture_atlas_keys: Vec<[u32; 9]>,
+    pub texture_atlas_keys: Vec<MeshKey>,
 
     /// The sprite's alpha mode.
     ///
@@ -387,10 +574,37 @@ pub struct Sprite3d
     ///   (though you can go out of bounds without issue)
     pub pivot: Option<Vec2>,
 
+    /// Per-frame data for the sprite's texture atlas: pivots for individual
+    /// frames (overriding `pivot`), and where trimmed frames sat in their
+    /// untrimmed image. `None` (default) treats every atlas rect as a whole
+    /// frame with the same pivot. Must be loaded before the sprite is spawned.
+    pub frames: Option<Handle<Sprite3dFrames>>,
+
     /// Whether the sprite should be rendered as double-sided.
     /// `true` (default) adds a second set of indices, describing the same tris
     /// in reverse order.
     pub double_sided: bool,
+
+    /// How thick to make the sprite, in metres. `0.` (default) is a flat
+    /// quad; anything more extrudes the sprite's opaque pixels into a slab,
+    /// with side faces coloured from the edge pixels -- eg. for held items.
+    /// The image must keep its pixels on the CPU (the default), and the side
+    /// faces ignore `Sprite::flip_x` and `flip_y`.
+    pub extrude_depth: f32,
+
+    /// Trims the mesh to a convex polygon around the sprite's visible pixels,
+    /// with at most this many vertices, rather than a full quad. Cuts the
+    /// overdraw of mostly transparent sprites, eg. particles. `None`
+    /// (default) uses a quad. Ignored when `extrude_depth` is set.
+    pub tight_mesh: Option<usize>,
+
+    /// How far to pull the UVs of atlas frames in from the edges of their
+    /// rects, in texels. eg. `0.5` samples pixel centres at the edges, so
+    /// linear filtering and mipmaps don't bleed in neighbouring frames -- an
+    /// alternative to padding the atlas with `pad_atlas`. `0.` (default)
+    /// samples the whole rect. Only quads are inset: ignored when
+    /// `extrude_depth` or `tight_mesh` is set.
+    pub uv_inset: f32,
 }
 
 impl Default for Sprite3d
@@ -400,9 +614,13 @@ impl Default for Sprite3d
         Self { texture_atlas_keys: Vec::new(),
                pixels_per_metre:   100.,
                pivot:              None,
+               frames:             None,
                alpha_mode:         DEFAULT_ALPHA_MODE,
                unlit:              false,
                double_sided:       true,
+               extrude_depth:      0.,
+               tight_mesh:         None,
+               uv_inset:           0.,
                emissive:           LinearRgba::BLACK, }
     }
 }
@@ -0,0 +1,183 @@
+use bevy::image::{TextureAccessError, TextureFormatPixelInfo};
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension};
+
+// texels not in any atlas frame.
+const NO_FRAME: u32 = u32::MAX;
+
+/// Generates a full chain of mipmaps for `image` on the CPU, replacing any it
+/// already has. Images loaded from PNGs only have the one level, so sprites
+/// far from a perspective camera shimmer and alias.
+///
+/// - `layout` keeps each level's texels to their own atlas frame, so distant
+///   sprites don't blend in their neighbours. Frames smaller than a level's
+///   texels fade out of it.
+/// - `alpha_cutoff` (eg. `Some(0.5)` for the default `AlphaMode::Mask(0.5)`)
+///   rescales each level's alpha so the same share of every frame passes the
+///   cutoff as in the full size image. Otherwise alpha tested sprites thin out
+///   and vanish as they shrink.
+///
+/// Colour is averaged weighted by alpha, so transparent texels don't darken
+/// the edges. The image's sampler decides how the levels are blended.
+pub fn generate_mipmaps(image: &mut Image,
+                        layout: Option<&TextureAtlasLayout>,
+                        alpha_cutoff: Option<f32>)
+                        -> Result<(), TextureAccessError>
+{
+    let format = image.texture_descriptor.format;
+    let size = image.size();
+    let pixel_size = format.pixel_size()?;
+
+    // which frame each texel of the full size image belongs to.
+    let mut frames = vec![NO_FRAME; (size.x * size.y) as usize];
+    for (i, rect) in layout.map_or(&[][..], |l| &l.textures).iter().enumerate() {
+        let rect = rect.intersect(URect::from_corners(UVec2::ZERO, size));
+        for y in rect.min.y..rect.max.y {
+            for x in rect.min.x..rect.max.x {
+                frames[(y * size.x + x) as usize] = i as u32;
+            }
+        }
+    }
+
+    let mut level = Level { size, texels: Vec::with_capacity(frames.len()) };
+    for y in 0..size.y {
+        for x in 0..size.x {
+            level.texels.push(image.get_color_at(x, y)?.to_linear());
+        }
+    }
+    let coverage = alpha_cutoff.map(|cutoff| frame_coverage(&level, 0, &frames, size, cutoff));
+
+    // build the levels alongside the image, so it's left untouched if
+    // encoding one fails.
+    let base = (size.x * size.y) as usize * pixel_size;
+    let mut data = image.data.as_deref().map_or(Vec::new(), |d| d[..base.min(d.len())].to_vec());
+    let mut count = 1;
+    while level.size.cmpgt(UVec2::ONE).any() {
+        level = downsample(&level, count, &frames, size);
+        if let (Some(cutoff), Some(coverage)) = (alpha_cutoff, &coverage) {
+            preserve_coverage(&mut level, count, &frames, size, cutoff, coverage);
+        }
+
+        let mut encoded = Image::new_fill(Extent3d { width: level.size.x, height: level.size.y, depth_or_array_layers: 1 },
+                                          TextureDimension::D2,
+                                          &vec![0; pixel_size],
+                                          format,
+                                          image.asset_usage);
+        for (i, colour) in level.texels.iter().enumerate() {
+            let (x, y) = (i as u32 % level.size.x, i as u32 / level.size.x);
+            encoded.set_color_at(x, y, Color::LinearRgba(*colour))?;
+        }
+        data.extend(encoded.data.unwrap_or_default());
+        count += 1;
+    }
+
+    image.data = Some(data);
+    image.texture_descriptor.mip_level_count = count;
+    Ok(())
+}
+
+struct Level
+{
+    size:   UVec2,
+    texels: Vec<LinearRgba>,
+}
+
+// the frame of texel `pos` in mip level `level`: whichever frame its centre
+// falls in, in the full size image.
+fn frame_at(pos: UVec2, level: u32, frames: &[u32], size: UVec2) -> u32
+{
+    let centre = ((pos.as_vec2() + 0.5) * (1u32 << level) as f32).as_uvec2().min(size - 1);
+    frames[(centre.y * size.x + centre.x) as usize]
+}
+
+// halve `source` (level `level - 1`) into level `level`, averaging each 2x2
+// block weighted by alpha and ignoring texels from other frames.
+fn downsample(source: &Level, level: u32, frames: &[u32], size: UVec2) -> Level
+{
+    let dest_size = (source.size / 2).max(UVec2::ONE);
+    let mut texels = Vec::with_capacity((dest_size.x * dest_size.y) as usize);
+    for y in 0..dest_size.y {
+        for x in 0..dest_size.x {
+            let frame = frame_at(UVec2::new(x, y), level, frames, size);
+            let block: Vec<UVec2> = [UVec2::ZERO, UVec2::X, UVec2::Y, UVec2::ONE].iter()
+                                                                        .map(|&d| (UVec2::new(x, y) * 2 + d).min(source.size - 1))
+                                                                        .collect();
+            let same: Vec<UVec2> = block.iter().copied().filter(|&p| frame_at(p, level - 1, frames, size) == frame).collect();
+            // tiny frames may not have any texels left, so take what's there.
+            let block = if same.is_empty() { block } else { same };
+
+            let (mut colour, mut alpha) = (Vec3::ZERO, 0.);
+            for p in &block {
+                let texel = source.texels[(p.y * source.size.x + p.x) as usize];
+                colour += texel.to_vec3() * texel.alpha;
+                alpha += texel.alpha;
+            }
+            let colour = match alpha > 0. {
+                true => colour / alpha,
+                // fully transparent: keep the plain average, for filtering.
+                false => block.iter().map(|p| source.texels[(p.y * source.size.x + p.x) as usize].to_vec3()).sum::<Vec3>() / block.len() as f32,
+            };
+            texels.push(LinearRgba::from_vec3(colour).with_alpha(alpha / block.len() as f32));
+        }
+    }
+    Level { size: dest_size, texels }
+}
+
+// the share of each frame's texels with alpha of at least `cutoff`, indexed
+// by `frame_slot`.
+fn frame_coverage(level: &Level, index: u32, frames: &[u32], size: UVec2, cutoff: f32) -> Vec<f32>
+{
+    let mut counts = Vec::new();
+    for (i, texel) in level.texels.iter().enumerate() {
+        let pos = UVec2::new(i as u32 % level.size.x, i as u32 / level.size.x);
+        let slot = frame_slot(frame_at(pos, index, frames, size));
+        if counts.len() <= slot {
+            counts.resize(slot + 1, (0, 0));
+        }
+        counts[slot].1 += 1;
+        if texel.alpha >= cutoff {
+            counts[slot].0 += 1;
+        }
+    }
+    counts.iter().map(|&(passed, total)| if total > 0 { passed as f32 / total as f32 } else { 0. }).collect()
+}
+
+// scale each frame's alpha in `level` so the share of texels passing `cutoff`
+// matches `coverage`, the full size image's.
+fn preserve_coverage(level: &mut Level, index: u32, frames: &[u32], size: UVec2, cutoff: f32, coverage: &[f32])
+{
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for i in 0..level.texels.len() {
+        let pos = UVec2::new(i as u32 % level.size.x, i as u32 / level.size.x);
+        let slot = frame_slot(frame_at(pos, index, frames, size));
+        if groups.len() <= slot {
+            groups.resize(slot + 1, Vec::new());
+        }
+        groups[slot].push(i);
+    }
+
+    for (slot, texels) in groups.iter().enumerate() {
+        let Some(&target) = coverage.get(slot) else {
+            continue;
+        };
+        if texels.is_empty() {
+            continue;
+        }
+        let covered = |scale: f32| {
+            texels.iter().filter(|&&i| level.texels[i].alpha * scale >= cutoff).count() as f32 / texels.len() as f32
+        };
+        // coverage only grows with the scale, so binary search for it.
+        let (mut low, mut high) = (0., 16.);
+        for _ in 0..16 {
+            let mid = (low + high) / 2.;
+            if covered(mid) < target { low = mid } else { high = mid }
+        }
+        for &i in texels {
+            let texel = &mut level.texels[i];
+            texel.alpha = (texel.alpha * high).min(1.);
+        }
+    }
+}
+
+// index into per-frame lists, with texels outside any frame first.
+fn frame_slot(frame: u32) -> usize { if frame == NO_FRAME { 0 } else { frame as usize + 1 } }
@@ -0,0 +1,93 @@
+use bevy::image::TextureFormatPixelInfo;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension};
+
+/// Adds `padding` pixels around every frame of a grid atlas, filled by
+/// repeating the frame's edge pixels outwards, and returns the padded image
+/// with a layout pointing at the frames' new positions.
+///
+/// Without padding, sampling near the edge of a frame (with mipmaps,
+/// filtering, or just floating point error) picks up colour from its
+/// neighbours, showing as seams between tiles. A pixel or two of bleed hides
+/// them.
+///
+/// Frames should lie on a grid, as with `TextureAtlasLayout::from_grid`; any
+/// existing gaps between them are kept. Returns `None` if the image's pixels
+/// aren't available on the CPU, or are in a compressed format.
+pub fn pad_atlas(image: &Image, layout: &TextureAtlasLayout, padding: u32) -> Option<(Image, TextureAtlasLayout)>
+{
+    let pixel_size = image.texture_descriptor.format.pixel_size().ok()?;
+
+    // every column and row of frames moves along by two paddings for each
+    // column or row before it, plus one for its own.
+    let mut columns: Vec<u32> = layout.textures.iter().map(|r| r.min.x).collect();
+    let mut rows: Vec<u32> = layout.textures.iter().map(|r| r.min.y).collect();
+    columns.sort_unstable();
+    columns.dedup();
+    rows.sort_unstable();
+    rows.dedup();
+    let shift = |rect: &URect| {
+        let column = columns.binary_search(&rect.min.x).unwrap() as u32;
+        let row = rows.binary_search(&rect.min.y).unwrap() as u32;
+        UVec2::new(column * 2 + 1, row * 2 + 1) * padding
+    };
+
+    let size = image.size() + UVec2::new(columns.len() as u32, rows.len() as u32) * padding * 2;
+    let mut padded = Image::new_fill(Extent3d { width: size.x, height: size.y, depth_or_array_layers: 1 },
+                                     TextureDimension::D2,
+                                     &vec![0; pixel_size],
+                                     image.texture_descriptor.format,
+                                     image.asset_usage);
+    padded.sampler = image.sampler.clone();
+
+    let mut textures = Vec::with_capacity(layout.textures.len());
+    for rect in &layout.textures {
+        let moved = URect::from_corners(rect.min + shift(rect), rect.max + shift(rect));
+        // each padded pixel takes the colour of the nearest pixel in the frame.
+        for y in moved.min.y - padding..moved.max.y + padding {
+            for x in moved.min.x - padding..moved.max.x + padding {
+                let source = (UVec2::new(x, y).as_ivec2() - shift(rect).as_ivec2())
+                    .clamp(rect.min.as_ivec2(), rect.max.as_ivec2() - 1)
+                    .as_uvec2();
+                let pixel = image.pixel_bytes(source.extend(0))?;
+                padded.pixel_bytes_mut(UVec3::new(x, y, 0))?.copy_from_slice(pixel);
+            }
+        }
+        textures.push(moved);
+    }
+
+    Some((padded, TextureAtlasLayout { size, textures }))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use bevy::asset::RenderAssetUsages;
+    use bevy::render::render_resource::TextureFormat;
+
+    use super::*;
+
+    #[test]
+    fn frames_bleed_their_edges()
+    {
+        // two 2x2 frames side by side, each pixel's red channel naming it.
+        let data = (0..2).flat_map(|y| (0..4).flat_map(move |x| [x * 10 + y, 0, 0, 255])).collect();
+        let image = Image::new(Extent3d { width: 4, height: 2, depth_or_array_layers: 1 },
+                               TextureDimension::D2,
+                               data,
+                               TextureFormat::Rgba8Unorm,
+                               RenderAssetUsages::default());
+        let layout = TextureAtlasLayout::from_grid(UVec2::splat(2), 2, 1, None, None);
+
+        let (padded, padded_layout) = pad_atlas(&image, &layout, 1).unwrap();
+        assert_eq!(padded.size(), UVec2::new(8, 4));
+        assert_eq!(padded_layout.size, UVec2::new(8, 4));
+        assert_eq!(padded_layout.textures, [URect::new(1, 1, 3, 3), URect::new(5, 1, 7, 3)]);
+
+        let red = |x, y| padded.pixel_bytes(UVec3::new(x, y, 0)).unwrap()[0];
+        // the frames themselves are moved unchanged.
+        assert_eq!([red(1, 1), red(2, 1), red(1, 2), red(6, 2)], [0, 10, 1, 31]);
+        // their borders repeat the nearest edge pixel, corners included.
+        assert_eq!([red(0, 0), red(3, 1), red(4, 1), red(7, 3)], [0, 10, 20, 31]);
+    }
+}
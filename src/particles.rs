@@ -0,0 +1,408 @@
+use bevy::math::VectorSpace;
+use bevy::prelude::*;
+
+use crate::{Sprite3d, Sprite3dInstanced, DEFAULT_ALPHA_MODE};
+
+/// A value that changes over a particle's life, as keys of `(t, value)`
+/// where `t` runs from `0.` at birth to `1.` at death. Values between keys
+/// are interpolated linearly, and held past the first and last keys.
+#[derive(Clone, Debug)]
+pub struct ParticleCurve<T>
+{
+    keys: Vec<(f32, T)>,
+}
+
+impl<T: VectorSpace<Scalar = f32>> ParticleCurve<T>
+{
+    /// Keys may be given in any order.
+    pub fn new(keys: impl IntoIterator<Item = (f32, T)>) -> Self
+    {
+        let mut keys: Vec<(f32, T)> = keys.into_iter().collect();
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { keys }
+    }
+
+    pub fn constant(value: T) -> Self { Self { keys: vec![(0., value)] } }
+
+    /// Goes from `start` at birth to `end` at death.
+    pub fn linear(start: T, end: T) -> Self { Self { keys: vec![(0., start), (1., end)] } }
+
+    pub fn sample(&self, t: f32) -> T
+    {
+        let next = self.keys.partition_point(|(key, _)| *key <= t);
+        match (next.checked_sub(1).map(|i| self.keys[i]), self.keys.get(next)) {
+            (Some((t0, a)), Some(&(t1, b))) => a.lerp(b, (t - t0) / (t1 - t0)),
+            (Some((_, a)), None) | (None, Some(&(_, a))) => a,
+            (None, None) => T::ZERO,
+        }
+    }
+}
+
+/// Spawns `Sprite3d` particles from its entity's position, and moves, ages,
+/// and fades them until they die.
+///
+/// Dead particles are hidden and kept for the emitter's next spawns rather
+/// than despawned, so their meshes and materials are only built once. Since
+/// particles are recycled, changing `sprite`'s texture atlas layout once the
+/// emitter has started isn't supported.
+#[derive(Component, Clone)]
+#[require(Transform)]
+pub struct Sprite3dEmitter
+{
+    /// The sprite each particle starts as. For instanced particles, its colour
+    /// is multiplied by `colour` and `alpha`.
+    pub sprite:           Sprite,
+    pub pixels_per_metre: f32,
+    pub alpha_mode:       AlphaMode,
+    pub unlit:            bool,
+
+    /// Draws the particles with [`Sprite3dInstanced`], which tints each one
+    /// for free. `false` (default) spawns regular sprites, which share one
+    /// material and ignore the `colour` and `alpha` curves.
+    pub instanced:        bool,
+
+    /// Particles spawned per second, while `emitting`.
+    pub rate:             f32,
+    pub emitting:         bool,
+
+    /// The most particles alive at once. Spawns past it are skipped.
+    pub max_particles:    usize,
+
+    /// Each particle lives a random time in this range, in seconds.
+    pub lifetime:         (f32, f32),
+
+    /// Initial velocity, in the emitter's space, in metres per second. Each
+    /// axis is randomised by up to `velocity_spread` either way.
+    pub velocity:         Vec3,
+    pub velocity_spread:  Vec3,
+
+    /// World-space acceleration, in metres per second squared.
+    pub gravity:          Vec3,
+
+    /// How quickly particles slow down. Velocity decays by `e^-drag` each
+    /// second.
+    pub drag:             f32,
+
+    /// Scale over the particles' lives, and tint and opacity for instanced
+    /// particles.
+    pub size:             ParticleCurve<f32>,
+    pub colour:           ParticleCurve<LinearRgba>,
+    pub alpha:            ParticleCurve<f32>,
+
+    /// Atlas indices to step through over the particles' lives, spread evenly.
+    /// Empty (default) keeps the sprite's own index.
+    pub flipbook:         Vec<usize>,
+
+    /// Turns the particles to face the same way as the camera. Otherwise they
+    /// keep the emitter's rotation from when they spawned.
+    pub billboard:        bool,
+
+    accumulator:          f32,
+    pending:              u32,
+    alive:                usize,
+    pool:                 Vec<Entity>,
+    rng:                  u64,
+}
+
+impl Sprite3dEmitter
+{
+    pub fn new(sprite: Sprite, rate: f32) -> Self
+    {
+        Self { sprite,
+               pixels_per_metre: 100.,
+               alpha_mode:       DEFAULT_ALPHA_MODE,
+               unlit:            true,
+               instanced:        false,
+               rate,
+               emitting:         true,
+               max_particles:    256,
+               lifetime:         (1., 1.),
+               velocity:         Vec3::Y,
+               velocity_spread:  Vec3::ZERO,
+               gravity:          Vec3::ZERO,
+               drag:             0.,
+               size:             ParticleCurve::constant(1.),
+               colour:           ParticleCurve::constant(LinearRgba::WHITE),
+               alpha:            ParticleCurve::constant(1.),
+               flipbook:         Vec::new(),
+               billboard:        true,
+               accumulator:      0.,
+               pending:          0,
+               alive:            0,
+               pool:             Vec::new(),
+               rng:              0 }
+    }
+
+    pub fn with_pixels_per_metre(mut self, pixels_per_metre: f32) -> Self
+    {
+        self.pixels_per_metre = pixels_per_metre;
+        self
+    }
+
+    pub fn with_alpha_mode(mut self, alpha_mode: AlphaMode) -> Self
+    {
+        self.alpha_mode = alpha_mode;
+        self
+    }
+
+    pub fn with_instanced(mut self, instanced: bool) -> Self
+    {
+        self.instanced = instanced;
+        self
+    }
+
+    pub fn with_max_particles(mut self, max_particles: usize) -> Self
+    {
+        self.max_particles = max_particles;
+        self
+    }
+
+    pub fn with_lifetime(mut self, min: f32, max: f32) -> Self
+    {
+        self.lifetime = (min, max);
+        self
+    }
+
+    pub fn with_velocity(mut self, velocity: Vec3, spread: Vec3) -> Self
+    {
+        self.velocity = velocity;
+        self.velocity_spread = spread;
+        self
+    }
+
+    pub fn with_gravity(mut self, gravity: Vec3) -> Self
+    {
+        self.gravity = gravity;
+        self
+    }
+
+    pub fn with_drag(mut self, drag: f32) -> Self
+    {
+        self.drag = drag;
+        self
+    }
+
+    pub fn with_size(mut self, size: ParticleCurve<f32>) -> Self
+    {
+        self.size = size;
+        self
+    }
+
+    pub fn with_colour(mut self, colour: ParticleCurve<LinearRgba>) -> Self
+    {
+        self.colour = colour;
+        self
+    }
+
+    pub fn with_alpha(mut self, alpha: ParticleCurve<f32>) -> Self
+    {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn with_flipbook(mut self, frames: impl IntoIterator<Item = usize>) -> Self
+    {
+        self.flipbook = frames.into_iter().collect();
+        self
+    }
+
+    pub fn with_billboard(mut self, billboard: bool) -> Self
+    {
+        self.billboard = billboard;
+        self
+    }
+
+    /// Spawns `count` particles when the emitter first updates, on top of
+    /// `rate`. eg. `Sprite3dEmitter::new(sprite, 0.).with_burst(50)` for a
+    /// one-off explosion.
+    pub fn with_burst(mut self, count: u32) -> Self
+    {
+        self.burst(count);
+        self
+    }
+
+    /// Seeds the emitter's random numbers, for repeatable effects. By default
+    /// each emitter is seeded from its entity.
+    pub fn with_seed(mut self, seed: u64) -> Self
+    {
+        self.rng = seed.max(1);
+        self
+    }
+
+    /// Spawns `count` particles on the next update.
+    pub fn burst(&mut self, count: u32) { self.pending += count; }
+
+    /// How many of the emitter's particles are currently alive.
+    pub fn alive(&self) -> usize { self.alive }
+
+    // uniform in 0..1 (splitmix64).
+    fn random(&mut self) -> f32
+    {
+        self.rng = self.rng.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        ((z ^ (z >> 31)) >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn random_range(&mut self, min: f32, max: f32) -> f32 { min + (max - min) * self.random() }
+
+    // set a particle's scale, tint, and frame for how far through its life it is.
+    fn style(&self, particle: &Sprite3dParticle, sprite: &mut Sprite, transform: &mut Transform, camera: Option<Quat>)
+    {
+        let t = (particle.age / particle.lifetime).clamp(0., 1.);
+        transform.scale = Vec3::splat(self.size.sample(t));
+        if let (true, Some(camera)) = (self.billboard, camera) {
+            transform.rotation = camera;
+        }
+
+        // regular sprites would need a material per tint, so only instanced
+        // ones follow the curves.
+        let colour = match self.instanced {
+            true => {
+                let tint = LinearRgba::from_vec4(self.sprite.color.to_linear().to_vec4() * self.colour.sample(t).to_vec4());
+                Color::from(tint.with_alpha(tint.alpha * self.alpha.sample(t)))
+            }
+            false => self.sprite.color,
+        };
+        if sprite.color != colour {
+            sprite.color = colour;
+        }
+        if let (Some(atlas), false) = (sprite.texture_atlas.as_mut(), self.flipbook.is_empty()) {
+            let frame = ((t * self.flipbook.len() as f32) as usize).min(self.flipbook.len() - 1);
+            if atlas.index != self.flipbook[frame] {
+                atlas.index = self.flipbook[frame];
+            }
+        }
+    }
+}
+
+/// A particle spawned by a [`Sprite3dEmitter`].
+#[derive(Component, Clone, Debug)]
+pub struct Sprite3dParticle
+{
+    emitter:  Entity,
+    age:      f32,
+    lifetime: f32,
+    velocity: Vec3,
+    alive:    bool,
+}
+
+impl Sprite3dParticle
+{
+    /// The emitter that spawned this particle.
+    pub fn emitter(&self) -> Entity { self.emitter }
+
+    /// Seconds since the particle spawned.
+    pub fn age(&self) -> f32 { self.age }
+
+    /// Seconds the particle lives for in total.
+    pub fn lifetime(&self) -> f32 { self.lifetime }
+
+    pub fn velocity(&self) -> Vec3 { self.velocity }
+
+    /// `false` while the particle is hidden, waiting to be reused.
+    pub fn is_alive(&self) -> bool { self.alive }
+}
+
+// Age and move every particle, returning dead ones to their emitter's pool,
+// then spawn new particles, reusing pooled ones first.
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_emitters(mut commands: Commands,
+                              time: Res<Time>,
+                              cameras: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+                              mut emitters: Query<(Entity, &mut Sprite3dEmitter, &GlobalTransform)>,
+                              mut particles: Query<(Entity,
+                                                    &mut Sprite3dParticle,
+                                                    &mut Sprite,
+                                                    &mut Transform,
+                                                    &mut Visibility)>)
+{
+    let dt = time.delta_secs();
+    let camera = cameras.iter().find(|(c, _)| c.is_active).map(|(_, t)| t.rotation());
+
+    for (entity, mut particle, mut sprite, mut transform, mut visibility) in particles.iter_mut() {
+        let emitter = emitters.get_mut(particle.emitter).ok();
+        if !particle.alive {
+            // pooled particles go with their emitter.
+            if emitter.is_none() {
+                commands.entity(entity).despawn();
+            }
+            continue;
+        }
+
+        particle.age += dt;
+        let Some((_, mut emitter, _)) = emitter else {
+            // orphaned particles live out their lives unstyled.
+            if particle.age >= particle.lifetime {
+                commands.entity(entity).despawn();
+            }
+            continue;
+        };
+        if particle.age >= particle.lifetime {
+            particle.alive = false;
+            *visibility = Visibility::Hidden;
+            emitter.alive = emitter.alive.saturating_sub(1);
+            emitter.pool.push(entity);
+            continue;
+        }
+
+        particle.velocity += emitter.gravity * dt;
+        particle.velocity *= (-emitter.drag * dt).exp();
+        transform.translation += particle.velocity * dt;
+        emitter.style(&particle, &mut sprite, &mut transform, camera);
+    }
+
+    for (entity, mut emitter, global) in emitters.iter_mut() {
+        if emitter.rng == 0 {
+            emitter.rng = entity.to_bits().max(1);
+        }
+        let mut count = std::mem::take(&mut emitter.pending);
+        if emitter.emitting {
+            emitter.accumulator += emitter.rate.max(0.) * dt;
+            count += emitter.accumulator as u32;
+            emitter.accumulator = emitter.accumulator.fract();
+        }
+        let count = (count as usize).min(emitter.max_particles.saturating_sub(emitter.alive));
+
+        let (_, rotation, translation) = global.to_scale_rotation_translation();
+        for _ in 0..count {
+            let spread = emitter.velocity_spread * Vec3::new(emitter.random() * 2. - 1.,
+                                                             emitter.random() * 2. - 1.,
+                                                             emitter.random() * 2. - 1.);
+            let (min, max) = emitter.lifetime;
+            let particle = Sprite3dParticle { emitter:  entity,
+                                              age:      0.,
+                                              lifetime: emitter.random_range(min, max).max(f32::EPSILON),
+                                              velocity: rotation * (emitter.velocity + spread),
+                                              alive:    true, };
+            let mut transform = Transform::from_translation(translation).with_rotation(rotation);
+            emitter.alive += 1;
+
+            // reuse a pooled particle if there is one.
+            if let Some((_, mut pooled, mut sprite, mut pooled_transform, mut visibility)) =
+                emitter.pool.pop().and_then(|e| particles.get_mut(e).ok())
+            {
+                if sprite.image != emitter.sprite.image {
+                    sprite.image = emitter.sprite.image.clone();
+                }
+                *pooled_transform = transform;
+                emitter.style(&particle, &mut sprite, &mut pooled_transform, camera);
+                *pooled = particle;
+                *visibility = Visibility::Inherited;
+                continue;
+            }
+
+            let mut sprite = emitter.sprite.clone();
+            emitter.style(&particle, &mut sprite, &mut transform, camera);
+            let sprite3d = Sprite3d { pixels_per_metre: emitter.pixels_per_metre,
+                                      alpha_mode:       emitter.alpha_mode,
+                                      unlit:            emitter.unlit,
+                                      ..default() };
+            let mut spawned = commands.spawn((sprite, sprite3d, transform, particle));
+            if emitter.instanced {
+                spawned.insert(Sprite3dInstanced);
+            }
+        }
+    }
+}
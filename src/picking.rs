@@ -0,0 +1,101 @@
+use bevy::asset::uuid_handle;
+use bevy::camera::visibility::RenderLayers;
+use bevy::picking::backend::ray::RayMap;
+use bevy::picking::backend::{HitData, PointerHits};
+use bevy::picking::mesh_picking::ray_cast::SimplifiedMesh;
+use bevy::picking::PickingSystems;
+use bevy::prelude::*;
+
+use crate::{Sprite3d, Sprite3dRaycast, Sprite3dRaycastSettings};
+
+// a handle that's never given a mesh. Mesh ray casts skip entities whose
+// meshes don't resolve, so sprites with it as their `SimplifiedMesh` are left
+// to this backend.
+const NO_MESH: Handle<Mesh> = uuid_handle!("c7d8e2a4-51b3-4f0e-8a6d-93e1f4b2c605");
+
+/// A picking backend for [`Sprite3d`]s which ignores their transparent
+/// pixels, so clicking the empty corner of a tree sprite doesn't pick the tree.
+/// Rays are tested against each sprite's frame, taking its atlas rect, pivot
+/// and flips into account, and hits are dropped if the texel there is below
+/// the sprite's alpha cutoff (see [`Sprite3dPickingSettings`]). The image's
+/// pixels must stay on the CPU for the alpha test, as they do by default.
+///
+/// Sprites are given an empty `SimplifiedMesh`, so mesh picking (and
+/// `MeshRayCast`) skip them rather than picking the whole quad.
+pub struct Sprite3dPickingPlugin;
+
+impl Plugin for Sprite3dPickingPlugin
+{
+    fn build(&self, app: &mut App)
+    {
+        app.init_resource::<Sprite3dPickingSettings>()
+           .register_required_components_with::<Sprite3d, SimplifiedMesh>(|| SimplifiedMesh(NO_MESH))
+           .add_systems(PreUpdate, update_sprite_hits.in_set(PickingSystems::Backend));
+    }
+}
+
+/// Settings for [`Sprite3dPickingPlugin`].
+#[derive(Resource, Clone, Debug)]
+pub struct Sprite3dPickingSettings
+{
+    /// Only pick through cameras marked with [`Sprite3dPickingCamera`], and
+    /// only pick sprites with a [`Pickable`]. `false` (default) picks any
+    /// visible sprite from any camera.
+    pub require_markers: bool,
+
+    /// The alpha below which pixels of blended sprites can't be picked.
+    /// Masked sprites use their own cutoff, and opaque ones are picked
+    /// anywhere on their quad. Defaults to `0.1`.
+    pub alpha_threshold: f32,
+}
+
+impl Default for Sprite3dPickingSettings
+{
+    fn default() -> Self { Self { require_markers: false, alpha_threshold: 0.1 } }
+}
+
+/// Marks a camera to pick sprites through, when
+/// [`Sprite3dPickingSettings::require_markers`] is set.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct Sprite3dPickingCamera;
+
+// Cast each pointer's rays against every visible sprite, and report the ones
+// they hit.
+#[allow(clippy::type_complexity)]
+fn update_sprite_hits(settings: Res<Sprite3dPickingSettings>,
+                      ray_map: Res<RayMap>,
+                      cameras: Query<(&Camera, Has<Sprite3dPickingCamera>, Option<&RenderLayers>)>,
+                      sprites: Query<(&ViewVisibility, Option<&RenderLayers>, Option<&Pickable>), With<Sprite3d>>,
+                      raycast: Sprite3dRaycast,
+                      mut hits: MessageWriter<PointerHits>)
+{
+    for (&ray_id, &ray) in ray_map.iter() {
+        let Ok((camera, marked, camera_layers)) = cameras.get(ray_id.camera) else {
+            continue;
+        };
+        if settings.require_markers && !marked {
+            continue;
+        }
+        let camera_layers = camera_layers.cloned().unwrap_or_default();
+        let default_layers = RenderLayers::default();
+
+        let filter = |entity| {
+            sprites.get(entity).is_ok_and(|(visibility, layers, pickable)| {
+                visibility.get()
+                && (!settings.require_markers || pickable.is_some())
+                && pickable.is_none_or(|p| p.is_hoverable)
+                && camera_layers.intersects(layers.unwrap_or(&default_layers))
+            })
+        };
+        let raycast_settings = Sprite3dRaycastSettings { blended_alpha: settings.alpha_threshold, ..default() }.with_filter(&filter);
+        let picks: Vec<(Entity, HitData)> =
+            raycast.cast_ray(ray, &raycast_settings)
+                   .into_iter()
+                   .map(|hit| (hit.entity, HitData::new(ray_id.camera, hit.distance, Some(hit.point), Some(hit.normal))))
+                   .collect();
+
+        if !picks.is_empty() {
+            hits.write(PointerHits::new(ray_id.pointer, picks, camera.order as f32));
+        }
+    }
+}
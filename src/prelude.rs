@@ -1 +1,60 @@
-pub use crate::{Sprite3d, Sprite3dPlugin};
+pub use crate::{AutotileRule,
+                AutotileRules,
+                DirectionalSprite3d,
+                HexLayout,
+                HexOrientation,
+                LevelTiles,
+                ParticleCurve,
+                PlaybackMode,
+                Sprite3d,
+                Sprite3dAnimation,
+                Sprite3dAtlas,
+                Sprite3dAutotile,
+                Sprite3dChunkLoaded,
+                Sprite3dChunkUnloaded,
+                Sprite3dClip,
+                Sprite3dClipFinished,
+                Sprite3dClipLooped,
+                Sprite3dEmitter,
+                Sprite3dFlipbook,
+                Sprite3dFlipbookMaterial,
+                Sprite3dFrameEvent,
+                Sprite3dFrames,
+                Sprite3dInstanced,
+                Sprite3dLevel,
+                Sprite3dParticle,
+                Sprite3dPlugin,
+                Sprite3dRayHit,
+                Sprite3dRaycast,
+                Sprite3dRaycastSettings,
+                Sprite3dStreamFocus,
+                Sprite3dTilemap,
+                TileAnimation,
+                TileGrid,
+                TilemapPlane,
+                TilemapStreaming,
+                generate_mipmaps,
+                pad_atlas};
+
+#[cfg(feature = "aseprite")]
+pub use crate::AsepriteLoaderSettings;
+
+#[cfg(feature = "ldtk")]
+pub use crate::{LdtkEntity,
+                LdtkWall,
+                Sprite3dLdtk,
+                Sprite3dLdtkProject};
+
+#[cfg(feature = "picking")]
+pub use crate::{Sprite3dPickingCamera,
+                Sprite3dPickingPlugin,
+                Sprite3dPickingSettings};
+
+#[cfg(feature = "texture_packer")]
+pub use crate::TexturePackerLoaderSettings;
+
+#[cfg(feature = "tiled")]
+pub use crate::{Sprite3dTiled,
+                Sprite3dTiledMap,
+                TiledObject,
+                TiledProperty};
@@ -0,0 +1,180 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::{trimmed_pivot, Sprite3d, Sprite3dFrames};
+
+/// Where a ray hit a sprite, from [`Sprite3dRaycast`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprite3dRayHit
+{
+    pub entity:   Entity,
+
+    /// Distance along the ray, in world units.
+    pub distance: f32,
+    pub point:    Vec3,
+
+    /// The normal of the side of the sprite that was hit.
+    pub normal:   Vec3,
+
+    /// Texture coordinates of the hit, within the whole image.
+    pub uv:       Vec2,
+
+    /// The pixel of the image that was hit, counting from its top-left.
+    pub texel:    UVec2,
+}
+
+/// Options for a [`Sprite3dRaycast`].
+#[derive(Clone, Copy)]
+pub struct Sprite3dRaycastSettings<'a>
+{
+    /// Ignore hits on texels below the sprite's alpha cutoff: its own for
+    /// `AlphaMode::Mask`, or `blended_alpha` for blended modes. Opaque
+    /// sprites are hit anywhere. `true` (default).
+    pub alpha_test:    bool,
+    pub blended_alpha: f32,
+
+    /// Only sprites it returns `true` for can be hit.
+    pub filter:        &'a dyn Fn(Entity) -> bool,
+}
+
+impl Default for Sprite3dRaycastSettings<'_>
+{
+    fn default() -> Self { Self { alpha_test: true, blended_alpha: 0.1, filter: &|_| true } }
+}
+
+impl<'a> Sprite3dRaycastSettings<'a>
+{
+    pub fn with_alpha_test(mut self, alpha_test: bool) -> Self
+    {
+        self.alpha_test = alpha_test;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: &'a dyn Fn(Entity) -> bool) -> Self
+    {
+        self.filter = filter;
+        self
+    }
+}
+
+/// Casts rays against visible [`Sprite3d`]s, without a physics engine, eg.
+/// to find which sprite a bullet hits first.
+///
+/// Sprites are tested against their frame's quad, using the same size, pivot
+/// and transform they're drawn with. Extruded and tight sprites are treated as
+/// their flat quad, and the alpha test needs the image's pixels kept on the CPU
+/// (the default) -- hits on images without them always count.
+#[derive(SystemParam)]
+pub struct Sprite3dRaycast<'w, 's>
+{
+    sprites: Query<'w, 's, (Entity, &'static Sprite, &'static Sprite3d, &'static GlobalTransform, &'static InheritedVisibility)>,
+    images:  Res<'w, Assets<Image>>,
+    layouts: Res<'w, Assets<TextureAtlasLayout>>,
+    frames:  Res<'w, Assets<Sprite3dFrames>>,
+}
+
+impl Sprite3dRaycast<'_, '_>
+{
+    /// Every sprite `ray` hits, nearest first.
+    pub fn cast_ray(&self, ray: Ray3d, settings: &Sprite3dRaycastSettings) -> Vec<Sprite3dRayHit>
+    {
+        let mut hits: Vec<Sprite3dRayHit> = self.sprites.iter()
+            .filter(|(entity, .., visibility)| visibility.get() && (settings.filter)(*entity))
+            .filter_map(|(entity, sprite, sprite3d, transform, _)| {
+                let threshold = settings.alpha_test.then(|| alpha_threshold(sprite3d, settings.blended_alpha)).flatten();
+                self.ray_sprite(ray, entity, sprite, sprite3d, transform, threshold)
+            })
+            .collect();
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        hits
+    }
+
+    /// The nearest sprite `ray` hits.
+    pub fn first_hit(&self, ray: Ray3d, settings: &Sprite3dRaycastSettings) -> Option<Sprite3dRayHit>
+    {
+        self.cast_ray(ray, settings).into_iter().next()
+    }
+
+    // intersect `ray` with the plane of a sprite's frame, as `bundle_builder`
+    // lays it out, ignoring texels with alpha below `alpha_threshold`.
+    fn ray_sprite(&self,
+                  ray: Ray3d,
+                  entity: Entity,
+                  sprite: &Sprite,
+                  sprite3d: &Sprite3d,
+                  transform: &GlobalTransform,
+                  alpha_threshold: Option<f32>)
+                  -> Option<Sprite3dRayHit>
+    {
+        let (images, layouts, frames) = (&self.images, &self.layouts, &self.frames);
+        let image = images.get(&sprite.image)?;
+        let (rect, index) = match &sprite.texture_atlas {
+            Some(atlas) => (*layouts.get(&atlas.layout)?.textures.get(atlas.index)?, Some(atlas.index)),
+            None => (URect::from_corners(UVec2::ZERO, image.size()), None),
+        };
+
+        // as in `bundle_builder`, frames can override and trim the pivot.
+        let mut pivot = sprite3d.pivot.unwrap_or(Vec2::splat(0.5));
+        if let (Some(frames), Some(i)) = (sprite3d.frames.as_ref().and_then(|f| frames.get(f)), index) {
+            pivot = frames.pivot(i).unwrap_or(pivot);
+            if let Some(trim) = frames.trim(i) {
+                pivot = trimmed_pivot(pivot, rect, trim);
+            }
+        }
+        let size = rect.size().as_vec2() / sprite3d.pixels_per_metre;
+
+        // the sprite lies on its local z = 0 plane, facing +z.
+        let world_from_local = transform.affine();
+        let local_from_world = world_from_local.inverse();
+        let origin = local_from_world.transform_point3(ray.origin);
+        let direction = local_from_world.transform_vector3(*ray.direction);
+        if direction.z.abs() <= f32::EPSILON || (!sprite3d.double_sided && direction.z > 0.) {
+            return None;
+        }
+        let t = -origin.z / direction.z;
+        if t < 0. {
+            return None;
+        }
+        let local = origin + direction * t;
+
+        // from the frame's bottom-left, 0 to 1.
+        let mut f = local.truncate() / size + pivot;
+        if f.cmplt(Vec2::ZERO).any() || f.cmpgt(Vec2::ONE).any() {
+            return None;
+        }
+        if sprite.flip_x {
+            f.x = 1. - f.x;
+        }
+        if sprite.flip_y {
+            f.y = 1. - f.y;
+        }
+        let pixel = rect.min.as_vec2() + Vec2::new(f.x, 1. - f.y) * rect.size().as_vec2();
+        let texel = pixel.as_uvec2().clamp(rect.min, rect.max.max(rect.min + 1) - 1);
+
+        if let (Some(threshold), Ok(colour)) = (alpha_threshold, image.get_color_at(texel.x, texel.y)) {
+            if colour.alpha() < threshold {
+                return None;
+            }
+        }
+
+        let point = world_from_local.transform_point3(local);
+        let normal = transform.rotation() * Vec3::Z * -direction.z.signum();
+        Some(Sprite3dRayHit { entity,
+                              distance: (point - ray.origin).dot(*ray.direction),
+                              point,
+                              normal,
+                              uv: pixel / image.size().as_vec2(),
+                              texel })
+    }
+}
+
+// the alpha below which a sprite's texels don't count as hit, going by how
+// it's drawn.
+fn alpha_threshold(sprite3d: &Sprite3d, blended: f32) -> Option<f32>
+{
+    match sprite3d.alpha_mode {
+        AlphaMode::Opaque => None,
+        AlphaMode::Mask(cutoff) => Some(cutoff),
+        _ => Some(blended),
+    }
+}
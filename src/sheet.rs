@@ -0,0 +1,241 @@
+use bevy::asset::ParseAssetPathError;
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::fmt;
+
+use crate::{FrameTrim, Sprite3dFrames};
+
+/// Errors from the sprite sheet loaders.
+#[derive(Debug)]
+pub enum Sprite3dAtlasLoaderError
+{
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Path(ParseAssetPathError),
+    /// The file is valid, but uses a feature these loaders don't handle.
+    Unsupported(String),
+    /// The file contradicts itself, eg. a tag runs past the last frame.
+    Invalid(String),
+}
+
+impl fmt::Display for Sprite3dAtlasLoaderError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Self::Io(e) => write!(f, "could not read sprite sheet: {e}"),
+            Self::Json(e) => write!(f, "could not parse sprite sheet: {e}"),
+            Self::Path(e) => write!(f, "invalid image path in sprite sheet: {e}"),
+            Self::Unsupported(what) => write!(f, "unsupported sprite sheet: {what}"),
+            Self::Invalid(what) => write!(f, "invalid sprite sheet: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for Sprite3dAtlasLoaderError {}
+
+impl From<std::io::Error> for Sprite3dAtlasLoaderError
+{
+    fn from(e: std::io::Error) -> Self { Self::Io(e) }
+}
+
+impl From<serde_json::Error> for Sprite3dAtlasLoaderError
+{
+    fn from(e: serde_json::Error) -> Self { Self::Json(e) }
+}
+
+impl From<ParseAssetPathError> for Sprite3dAtlasLoaderError
+{
+    fn from(e: ParseAssetPathError) -> Self { Self::Path(e) }
+}
+
+
+// ---------------------------------------------------------------------------
+// The "JSON hash" / "JSON array" sprite sheet format, originally from
+// TexturePacker and also exported by Aseprite and others. Tags, slices and
+// frame durations are only read by the Aseprite loader.
+
+#[derive(Deserialize, Clone, Copy, Default, Debug)]
+pub(crate) struct JsonRect
+{
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, Debug)]
+pub(crate) struct JsonSize
+{
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, Debug)]
+pub(crate) struct JsonPoint
+{
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct JsonFrame
+{
+    /// only present in the "array" flavour, filled in from the key otherwise.
+    pub filename:           Option<String>,
+    pub frame:              JsonRect,
+    #[serde(default)]
+    pub rotated:            bool,
+    #[serde(default)]
+    pub trimmed:            bool,
+    pub sprite_source_size: Option<JsonRect>,
+    pub source_size:        Option<JsonSize>,
+    /// normalised, from the top-left of the untrimmed frame.
+    pub pivot:              Option<JsonPoint>,
+    /// milliseconds
+    #[cfg_attr(not(feature = "aseprite"), allow(dead_code))]
+    pub duration:           Option<f32>,
+}
+
+impl JsonFrame
+{
+    // the size of the frame before any trimming.
+    pub fn source_size(&self) -> UVec2
+    {
+        match self.source_size {
+            Some(s) => UVec2::new(s.w, s.h),
+            None => UVec2::new(self.frame.w, self.frame.h),
+        }
+    }
+
+    pub fn trim(&self) -> Option<FrameTrim>
+    {
+        if !self.trimmed {
+            return None;
+        }
+        let offset = self.sprite_source_size.map_or(UVec2::ZERO, |r| UVec2::new(r.x, r.y));
+        Some(FrameTrim { offset, source_size: self.source_size() })
+    }
+
+    /// The frame's own pivot, converted to Sprite3d's bottom-left origin.
+    pub fn pivot(&self) -> Option<Vec2> { self.pivot.map(|p| Vec2::new(p.x, 1. - p.y)) }
+}
+
+#[cfg_attr(not(feature = "aseprite"), allow(dead_code))]
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct JsonTag
+{
+    pub name:      String,
+    pub from:      usize,
+    pub to:        usize,
+    #[serde(default)]
+    pub direction: String,
+    pub repeat:    Option<String>,
+}
+
+#[cfg_attr(not(feature = "aseprite"), allow(dead_code))]
+#[derive(Deserialize, Debug)]
+pub(crate) struct JsonSliceKey
+{
+    pub frame:  usize,
+    pub bounds: JsonRect,
+    pub pivot:  Option<JsonPoint>,
+}
+
+#[cfg_attr(not(feature = "aseprite"), allow(dead_code))]
+#[derive(Deserialize, Debug)]
+pub(crate) struct JsonSlice
+{
+    pub name: String,
+    #[serde(default)]
+    pub keys: Vec<JsonSliceKey>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct JsonMeta
+{
+    #[serde(default)]
+    pub image:      String,
+    pub size:       Option<JsonSize>,
+    #[cfg_attr(not(feature = "aseprite"), allow(dead_code))]
+    #[serde(default)]
+    pub frame_tags: Vec<JsonTag>,
+    #[cfg_attr(not(feature = "aseprite"), allow(dead_code))]
+    #[serde(default)]
+    pub slices:     Vec<JsonSlice>,
+}
+
+#[derive(Debug)]
+pub(crate) struct JsonSheet
+{
+    pub frames: Vec<JsonFrame>,
+    pub meta:   JsonMeta,
+}
+
+impl JsonSheet
+{
+    pub fn parse(bytes: &[u8]) -> Result<Self, Sprite3dAtlasLoaderError>
+    {
+        #[derive(Deserialize)]
+        struct Raw
+        {
+            frames: serde_json::Value,
+            #[serde(default)]
+            meta:   JsonMeta,
+        }
+
+        let raw: Raw = serde_json::from_slice(bytes)?;
+
+        // the "hash" flavour keys frames by filename, in export order, the
+        // "array" flavour lists them directly.
+        let frames = match raw.frames {
+            serde_json::Value::Array(frames) => frames.into_iter()
+                                                      .map(serde_json::from_value)
+                                                      .collect::<Result<Vec<JsonFrame>, _>>()?,
+            serde_json::Value::Object(frames) => frames.into_iter().map(|(filename, f)| {
+                let mut frame: JsonFrame = serde_json::from_value(f)?;
+                frame.filename.get_or_insert(filename);
+                Ok(frame)
+            }).collect::<Result<Vec<JsonFrame>, serde_json::Error>>()?,
+            _ => return Err(Sprite3dAtlasLoaderError::Unsupported("`frames` must be an array or object".into())),
+        };
+
+        if frames.iter().any(|f| f.rotated) {
+            return Err(Sprite3dAtlasLoaderError::Unsupported("rotated frames".into()));
+        }
+
+        Ok(Self { frames, meta: raw.meta })
+    }
+
+    pub fn layout(&self) -> TextureAtlasLayout
+    {
+        let size = match self.meta.size {
+            Some(s) => UVec2::new(s.w, s.h),
+            // no size given, so assume the image ends at the furthest frame.
+            None => self.frames.iter().fold(UVec2::ZERO, |size, f| {
+                size.max(UVec2::new(f.frame.x + f.frame.w, f.frame.y + f.frame.h))
+            }),
+        };
+        let mut layout = TextureAtlasLayout::new_empty(size);
+        for f in &self.frames {
+            layout.add_texture(URect::new(f.frame.x, f.frame.y, f.frame.x + f.frame.w, f.frame.y + f.frame.h));
+        }
+        layout
+    }
+
+    pub fn frames(&self) -> Sprite3dFrames
+    {
+        Sprite3dFrames { pivots: self.frames.iter().map(JsonFrame::pivot).collect(),
+                         trims:  self.frames.iter().map(JsonFrame::trim).collect(), }
+    }
+
+    /// frame durations in seconds, defaulting to 0.1s where none is given.
+    #[cfg_attr(not(feature = "aseprite"), allow(dead_code))]
+    pub fn duration(&self, frame: usize) -> f32
+    {
+        self.frames.get(frame).and_then(|f| f.duration).map_or(0.1, |ms| ms / 1000.)
+    }
+}
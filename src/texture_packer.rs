@@ -0,0 +1,87 @@
+use bevy::asset::{io::Reader, AssetLoader, LoadContext};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::sheet::JsonSheet;
+use crate::{Sprite3dAtlas, Sprite3dAtlasLoaderError, Sprite3dClip};
+
+/// Loads packed atlases in the TexturePacker "JSON (Hash)" / "JSON (Array)"
+/// format, which most other packing tools can also export, as a
+/// [`Sprite3dAtlas`].
+///
+/// Trimmed frames keep their `spriteSourceSize` / `sourceSize` in
+/// [`Sprite3dAtlas::frames`](crate::Sprite3dAtlas::frames), along with frame
+/// pivots if they were exported, so that sprites using them stay anchored to
+/// the untrimmed frame.
+///
+/// Registered for the `.tp.json` extension, so it doesn't claim every `.json`
+/// asset (including Aseprite's).
+#[derive(Default, TypePath)]
+pub struct TexturePackerLoader;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TexturePackerLoaderSettings
+{
+    /// Group frames named like `walk_01.png`, `walk_02.png`, ... into clips
+    /// (here called `"walk"`), each frame lasting `frame_duration` seconds.
+    /// `true` by default.
+    pub clips_from_filenames: bool,
+
+    pub frame_duration:       f32,
+}
+
+impl Default for TexturePackerLoaderSettings
+{
+    fn default() -> Self { Self { clips_from_filenames: true, frame_duration: 0.1 } }
+}
+
+impl AssetLoader for TexturePackerLoader
+{
+    type Asset = Sprite3dAtlas;
+    type Settings = TexturePackerLoaderSettings;
+    type Error = Sprite3dAtlasLoaderError;
+
+    async fn load(&self,
+                  reader: &mut dyn Reader,
+                  settings: &TexturePackerLoaderSettings,
+                  load_context: &mut LoadContext<'_>)
+                  -> Result<Sprite3dAtlas, Sprite3dAtlasLoaderError>
+    {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let sheet = JsonSheet::parse(&bytes)?;
+
+        let image_path = load_context.path().resolve_embed(&sheet.meta.image)?;
+        let image = load_context.load(image_path);
+        let layout = load_context.add_labeled_asset("layout".into(), sheet.layout());
+        let frames = load_context.add_labeled_asset("frames".into(), sheet.frames());
+
+        let mut clips: Vec<Sprite3dClip> = Vec::new();
+        if settings.clips_from_filenames {
+            for (i, frame) in sheet.frames.iter().enumerate() {
+                let Some(name) = frame.filename.as_deref().and_then(clip_name) else { continue };
+                match clips.iter_mut().find(|c| c.name == name) {
+                    Some(clip) => clip.frames.push(i),
+                    None => clips.push(Sprite3dClip::new(name, vec![i], settings.frame_duration)),
+                }
+            }
+        }
+
+        Ok(Sprite3dAtlas { image, layout, frames, clips })
+    }
+
+    fn extensions(&self) -> &[&str] { &["tp.json"] }
+}
+
+// "walk_01.png" -> "walk". Names without a trailing frame number aren't part
+// of an animation.
+fn clip_name(filename: &str) -> Option<&str>
+{
+    let stem = filename.rsplit_once('.').map_or(filename, |(stem, _)| stem);
+    let name = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+    if name.len() == stem.len() {
+        return None;
+    }
+    let name = name.trim_end_matches(['_', '-', ' ']);
+    (!name.is_empty()).then_some(name)
+}
@@ -0,0 +1,124 @@
+use bevy::asset::RenderAssetUsages;
+use bevy::mesh::{Indices, PrimitiveTopology};
+use bevy::prelude::*;
+
+use crate::extrude::solid_pixels;
+
+// any visible pixel counts, so the soft edges of blended sprites aren't cut.
+const VISIBLE_ALPHA: f32 = 1. / 255.;
+
+// Build a convex polygon covering the visible pixels of `rect` in `image`,
+// with at most `max_vertices` vertices (and at least 3), instead of a quad.
+//
+// `size` is the world-space size of the rect, and `pivot` is relative to it.
+// Returns `None` if the rect is empty or its pixels can't be read on the CPU.
+pub(crate) fn tight_mesh(image: &Image,
+                         rect: URect,
+                         size: Vec2,
+                         pivot: Vec2,
+                         max_vertices: usize,
+                         double_sided: bool)
+                         -> Option<Mesh>
+{
+    let image_size = image.size().as_vec2();
+    let rect_size = rect.size();
+    let solid = solid_pixels(image, rect, VISIBLE_ALPHA)?;
+
+    // the outer corners of each row's leftmost and rightmost visible pixels,
+    // in pixels from the rect's bottom-left.
+    let mut points = Vec::new();
+    for (y, row) in solid.chunks(rect_size.x as usize).enumerate() {
+        let (Some(left), Some(right)) = (row.iter().position(|&s| s), row.iter().rposition(|&s| s)) else {
+            continue;
+        };
+        let (bottom, top) = ((rect_size.y as usize - y - 1) as f32, (rect_size.y as usize - y) as f32);
+        for x in [left as f32, right as f32 + 1.] {
+            points.extend([Vec2::new(x, bottom), Vec2::new(x, top)]);
+        }
+    }
+    let hull = reduce(convex_hull(points), max_vertices, rect_size.as_vec2());
+    if hull.len() < 3 {
+        return None;
+    }
+
+    let mut positions: Vec<[f32; 3]> = hull.iter()
+                                           .map(|p| ((*p / rect_size.as_vec2() - pivot) * size).extend(0.).to_array())
+                                           .collect();
+    let mut uvs: Vec<[f32; 2]> = hull.iter()
+                                     .map(|p| ((rect.min.as_vec2() + Vec2::new(p.x, rect_size.y as f32 - p.y)) / image_size).to_array())
+                                     .collect();
+
+    // the hull is convex and anticlockwise, so a fan from its first vertex
+    // faces +z.
+    let n = hull.len() as u32;
+    let mut indices: Vec<u32> = (1..n - 1).flat_map(|i| [0, i, i + 1]).collect();
+    let mut normals = vec![[0., 0., 1.]; hull.len()];
+    if double_sided {
+        indices.extend((1..n - 1).flat_map(|i| [n, n + i + 1, n + i]));
+        normals.extend(vec![[0., 0., -1.]; hull.len()]);
+        positions.extend_from_within(..);
+        uvs.extend_from_within(..);
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default());
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.insert_indices(Indices::U32(indices));
+    Some(mesh)
+}
+
+// the anticlockwise convex hull of `points`, without collinear vertices
+// (Andrew's monotone chain).
+fn convex_hull(mut points: Vec<Vec2>) -> Vec<Vec2>
+{
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let half = |points: &mut dyn Iterator<Item = &Vec2>| {
+        let mut hull: Vec<Vec2> = Vec::new();
+        for &p in points {
+            while hull.len() >= 2 && (hull[hull.len() - 1] - hull[hull.len() - 2]).perp_dot(p - hull[hull.len() - 2]) <= 0. {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        hull.pop();
+        hull
+    };
+    let mut hull = half(&mut points.iter());
+    hull.extend(half(&mut points.iter().rev()));
+    hull
+}
+
+// cut `hull` down to `max` vertices by replacing edges with the point where
+// the edges either side of them meet, picking whichever adds the least area
+// each time. The hull only grows, so it still covers every visible pixel, and
+// it stays within `bounds` so it never samples a neighbouring atlas frame.
+fn reduce(mut hull: Vec<Vec2>, max: usize, bounds: Vec2) -> Vec<Vec2>
+{
+    while hull.len() > max.max(3) {
+        let n = hull.len();
+        let best = (0..n).filter_map(|i| {
+            let (prev, a, b, next) = (hull[(i + n - 1) % n], hull[i], hull[(i + 1) % n], hull[(i + 2) % n]);
+            let (into, out) = (a - prev, next - b);
+            // parallel or diverging neighbours never meet past this edge.
+            let turn = into.perp_dot(out);
+            if turn <= f32::EPSILON {
+                return None;
+            }
+            let p = a + into * ((b - a).perp_dot(out) / turn);
+            let inside = p.cmpge(Vec2::splat(-1e-3)).all() && p.cmple(bounds + 1e-3).all();
+            inside.then(|| ((b - a).perp_dot(p - a).abs(), i, p.clamp(Vec2::ZERO, bounds)))
+        }).min_by(|x, y| x.0.total_cmp(&y.0));
+
+        let Some((_, i, p)) = best else {
+            break;
+        };
+        hull[i] = p;
+        hull.remove((i + 1) % n);
+    }
+    hull
+}
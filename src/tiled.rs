@@ -0,0 +1,800 @@
+use base64::Engine;
+use bevy::asset::{io::Reader, AssetLoader, AssetPath, LoadContext, ParseAssetPathError, ReadAssetBytesError};
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+use roxmltree::Node;
+use std::fmt;
+use std::io::Read;
+
+use crate::{Sprite3d, Sprite3dAnimation, Sprite3dClip, Sprite3dTilemap, TileAnimation, TilemapPlane};
+
+// the top bits of a gid say how the tile is flipped; the rest is the tile.
+const GID_MASK: u32 = 0x0fff_ffff;
+const FLIP_X: u32 = 0x8000_0000;
+const FLIP_Y: u32 = 0x4000_0000;
+
+/// Loads maps made in the [Tiled](https://www.mapeditor.org) editor as a
+/// [`Sprite3dTiledMap`], along with their tilesets (embedded or external
+/// `.tsx` files) and the tileset images. Spawn one with [`Sprite3dTiled`].
+///
+/// Supports orthogonal, finite maps with tile data stored as CSV, XML or
+/// base64 (uncompressed, zlib or gzip), and tilesets built from a single
+/// image. Flipped tiles are drawn unflipped when tile layers are merged.
+///
+/// Registered for the `.tmx` extension.
+#[derive(Default, TypePath)]
+pub struct TiledLoader;
+
+/// A custom property set on a map, layer, tile or object in Tiled.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TiledProperty
+{
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    /// Strings, and every other type (colours, files, objects, ...) in the
+    /// form Tiled saved them.
+    String(String),
+}
+
+pub type TiledProperties = HashMap<String, TiledProperty>;
+
+/// A tileset used by a [`Sprite3dTiledMap`], sliced into an atlas layout
+/// where each tile's atlas index is its id within the tileset.
+#[derive(Clone, Debug)]
+pub struct TiledTileset
+{
+    pub name:       String,
+
+    /// The gid of the tileset's first tile within the map.
+    pub first_gid:  u32,
+    pub tile_count: u32,
+
+    /// The size of each tile, in pixels.
+    pub tile_size:  UVec2,
+    pub image:      Handle<Image>,
+    pub layout:     Handle<TextureAtlasLayout>,
+
+    /// Tile animations, keyed by tile id.
+    pub animations: HashMap<usize, TileAnimation>,
+
+    /// Custom properties of individual tiles, keyed by tile id.
+    pub properties: HashMap<usize, TiledProperties>,
+}
+
+impl TiledTileset
+{
+    /// This tileset's tile id for `gid`, if it belongs to the tileset.
+    pub fn tile_id(&self, gid: u32) -> Option<usize>
+    {
+        let gid = gid & GID_MASK;
+        (gid >= self.first_gid && gid - self.first_gid < self.tile_count).then(|| (gid - self.first_gid) as usize)
+    }
+}
+
+/// A layer of tiles in a [`Sprite3dTiledMap`].
+#[derive(Clone, Debug)]
+pub struct TiledTileLayer
+{
+    pub name:       String,
+
+    /// The layer's offset, in pixels, including those of any groups it's in.
+    pub offset:     Vec2,
+    pub visible:    bool,
+
+    /// Row by row, the gid of each tile, or `0` for empty tiles. Flip flags
+    /// are left in the top bits.
+    pub tiles:      Vec<u32>,
+    pub properties: TiledProperties,
+}
+
+/// A layer of objects in a [`Sprite3dTiledMap`].
+#[derive(Clone, Debug)]
+pub struct TiledObjectLayer
+{
+    pub name:       String,
+    pub offset:     Vec2,
+    pub visible:    bool,
+    pub objects:    Vec<TiledObject>,
+    pub properties: TiledProperties,
+}
+
+#[derive(Clone, Debug)]
+pub enum TiledLayer
+{
+    Tiles(TiledTileLayer),
+    Objects(TiledObjectLayer),
+}
+
+impl TiledLayer
+{
+    pub fn name(&self) -> &str
+    {
+        match self {
+            TiledLayer::Tiles(layer) => &layer.name,
+            TiledLayer::Objects(layer) => &layer.name,
+        }
+    }
+
+    pub fn properties(&self) -> &TiledProperties
+    {
+        match self {
+            TiledLayer::Tiles(layer) => &layer.properties,
+            TiledLayer::Objects(layer) => &layer.properties,
+        }
+    }
+}
+
+/// An object from an object layer. Spawned maps attach this to each object's
+/// entity, so its custom properties can be read by game code.
+#[derive(Component, Clone, Debug)]
+pub struct TiledObject
+{
+    pub id:         u32,
+    pub name:       String,
+
+    /// The object's class (called "type" before Tiled 1.9).
+    pub class:      String,
+
+    /// The object's position within its layer, in pixels. For tile objects
+    /// this is the bottom-left corner, otherwise the top-left.
+    pub position:   Vec2,
+    pub size:       Vec2,
+
+    /// Clockwise rotation, in degrees.
+    pub rotation:   f32,
+
+    /// The tile shown by tile objects, with flip flags.
+    pub gid:        Option<u32>,
+    pub visible:    bool,
+    pub properties: TiledProperties,
+}
+
+/// A map loaded by [`TiledLoader`].
+///
+/// Wait for it to be loaded *with dependencies* before using the tilesets'
+/// images -- [`Sprite3dTiled`] does this for you.
+#[derive(Asset, TypePath, Debug)]
+pub struct Sprite3dTiledMap
+{
+    /// The size of the map, in tiles.
+    pub size:       UVec2,
+
+    /// The size of the map's grid, in pixels.
+    pub tile_size:  UVec2,
+    pub tilesets:   Vec<TiledTileset>,
+
+    /// Every tile and object layer, bottom to top, with groups flattened.
+    pub layers:     Vec<TiledLayer>,
+    pub properties: TiledProperties,
+
+    #[dependency]
+    images:         Vec<Handle<Image>>,
+}
+
+impl Sprite3dTiledMap
+{
+    /// The tileset `gid` belongs to, if any.
+    pub fn tileset(&self, gid: u32) -> Option<&TiledTileset>
+    {
+        self.tilesets.iter().find(|tileset| tileset.tile_id(gid).is_some())
+    }
+
+    /// The layer called `name`, if the map has one.
+    pub fn layer(&self, name: &str) -> Option<&TiledLayer> { self.layers.iter().find(|l| l.name() == name) }
+}
+
+
+/// Errors from [`TiledLoader`].
+#[derive(Debug)]
+pub enum TiledLoaderError
+{
+    Io(std::io::Error),
+    Xml(roxmltree::Error),
+    Path(ParseAssetPathError),
+    /// An external tileset couldn't be read.
+    Tileset(ReadAssetBytesError),
+    /// The file is missing something, or has a value that doesn't parse.
+    Invalid(String),
+    /// The file is valid, but uses a feature this loader doesn't handle.
+    Unsupported(String),
+}
+
+impl fmt::Display for TiledLoaderError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Self::Io(e) => write!(f, "could not read tiled map: {e}"),
+            Self::Xml(e) => write!(f, "could not parse tiled map: {e}"),
+            Self::Path(e) => write!(f, "invalid path in tiled map: {e}"),
+            Self::Tileset(e) => write!(f, "could not read tileset: {e}"),
+            Self::Invalid(what) => write!(f, "invalid tiled map: {what}"),
+            Self::Unsupported(what) => write!(f, "unsupported tiled map: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for TiledLoaderError {}
+
+impl From<std::io::Error> for TiledLoaderError
+{
+    fn from(e: std::io::Error) -> Self { Self::Io(e) }
+}
+
+impl From<roxmltree::Error> for TiledLoaderError
+{
+    fn from(e: roxmltree::Error) -> Self { Self::Xml(e) }
+}
+
+impl From<ParseAssetPathError> for TiledLoaderError
+{
+    fn from(e: ParseAssetPathError) -> Self { Self::Path(e) }
+}
+
+impl From<ReadAssetBytesError> for TiledLoaderError
+{
+    fn from(e: ReadAssetBytesError) -> Self { Self::Tileset(e) }
+}
+
+
+impl AssetLoader for TiledLoader
+{
+    type Asset = Sprite3dTiledMap;
+    type Settings = ();
+    type Error = TiledLoaderError;
+
+    async fn load(&self,
+                  reader: &mut dyn Reader,
+                  _settings: &(),
+                  load_context: &mut LoadContext<'_>)
+                  -> Result<Sprite3dTiledMap, TiledLoaderError>
+    {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let text = String::from_utf8(bytes).map_err(|_| invalid("map isn't utf-8"))?;
+
+        // read any external tilesets first, so that no xml is held across an
+        // await.
+        let sources: Vec<Option<String>> = {
+            let doc = roxmltree::Document::parse(&text)?;
+            children(doc.root_element(), "tileset").map(|ts| ts.attribute("source").map(str::to_owned)).collect()
+        };
+        let mut external = Vec::new();
+        for source in sources {
+            external.push(match source {
+                Some(source) => {
+                    let path = load_context.path().resolve_embed(&source)?;
+                    let bytes = load_context.read_asset_bytes(path.clone()).await?;
+                    let text = String::from_utf8(bytes).map_err(|_| invalid(&format!("{source} isn't utf-8")))?;
+                    Some((path, text))
+                }
+                None => None,
+            });
+        }
+
+        let doc = roxmltree::Document::parse(&text)?;
+        let map = doc.root_element();
+        if map.tag_name().name() != "map" {
+            return Err(invalid("expected a <map>"));
+        }
+        if let Some(orientation) = map.attribute("orientation").filter(|o| *o != "orthogonal") {
+            return Err(TiledLoaderError::Unsupported(format!("{orientation} maps")));
+        }
+        if map.attribute("infinite") == Some("1") {
+            return Err(TiledLoaderError::Unsupported("infinite maps".into()));
+        }
+
+        let size = UVec2::new(attr(map, "width")?, attr(map, "height")?);
+        let tile_size = UVec2::new(attr(map, "tilewidth")?, attr(map, "tileheight")?);
+
+        let map_path = load_context.path().clone();
+        let mut tilesets = Vec::new();
+        for (i, (node, external)) in children(map, "tileset").zip(&external).enumerate() {
+            let first_gid = attr(node, "firstgid")?;
+            let tileset = match external {
+                Some((path, text)) => {
+                    let doc = roxmltree::Document::parse(text)?;
+                    parse_tileset(doc.root_element(), first_gid, i, path, load_context)?
+                }
+                None => parse_tileset(node, first_gid, i, &map_path, load_context)?,
+            };
+            tilesets.push(tileset);
+        }
+
+        let mut layers = Vec::new();
+        parse_layers(map, Vec2::ZERO, true, size, &mut layers)?;
+
+        let images = tilesets.iter().map(|t| t.image.clone()).collect();
+        Ok(Sprite3dTiledMap { size, tile_size, tilesets, layers, properties: parse_properties(map), images })
+    }
+
+    fn extensions(&self) -> &[&str] { &["tmx"] }
+}
+
+fn invalid(what: &str) -> TiledLoaderError { TiledLoaderError::Invalid(what.into()) }
+
+fn children<'a, 'i>(node: Node<'a, 'i>, tag: &'static str) -> impl Iterator<Item = Node<'a, 'i>>
+{
+    node.children().filter(move |n| n.has_tag_name(tag))
+}
+
+// a required attribute.
+fn attr<T: std::str::FromStr>(node: Node, name: &str) -> Result<T, TiledLoaderError>
+{
+    let value = node.attribute(name)
+                    .ok_or_else(|| invalid(&format!("<{}> is missing `{name}`", node.tag_name().name())))?;
+    value.parse().map_err(|_| invalid(&format!("bad `{name}` on <{}>: {value}", node.tag_name().name())))
+}
+
+// an optional attribute, falling back to `default`.
+fn attr_or<T: std::str::FromStr>(node: Node, name: &str, default: T) -> Result<T, TiledLoaderError>
+{
+    if node.has_attribute(name) { attr(node, name) } else { Ok(default) }
+}
+
+fn parse_tileset(node: Node,
+                 first_gid: u32,
+                 index: usize,
+                 path: &AssetPath,
+                 load_context: &mut LoadContext)
+                 -> Result<TiledTileset, TiledLoaderError>
+{
+    let name = node.attribute("name").unwrap_or_default().to_owned();
+    let tile_size = UVec2::new(attr(node, "tilewidth")?, attr(node, "tileheight")?);
+    let spacing: u32 = attr_or(node, "spacing", 0)?;
+    let margin: u32 = attr_or(node, "margin", 0)?;
+    let tile_count: u32 = attr(node, "tilecount")?;
+    let columns: u32 = attr(node, "columns")?;
+
+    let image = children(node, "image").next()
+                                       .ok_or_else(|| TiledLoaderError::Unsupported(format!("tileset {name} isn't a single image (image collections)")))?;
+    let image = load_context.load(path.resolve_embed(image.attribute("source").unwrap_or_default())?);
+
+    let rows = tile_count.div_ceil(columns.max(1));
+    let layout = TextureAtlasLayout::from_grid(tile_size, columns, rows, Some(UVec2::splat(spacing)), Some(UVec2::splat(margin)));
+    let layout = load_context.add_labeled_asset(format!("tileset{index}/layout"), layout);
+
+    let mut animations = HashMap::default();
+    let mut properties = HashMap::default();
+    for tile in children(node, "tile") {
+        let id: usize = attr(tile, "id")?;
+        if let Some(animation) = children(tile, "animation").next() {
+            let frames = children(animation, "frame").map(|frame| {
+                let duration: f32 = attr(frame, "duration")?;
+                Ok((attr(frame, "tileid")?, duration / 1000.))
+            }).collect::<Result<Vec<_>, TiledLoaderError>>()?;
+            animations.insert(id, TileAnimation::from_frames(frames));
+        }
+        let tile_properties = parse_properties(tile);
+        if !tile_properties.is_empty() {
+            properties.insert(id, tile_properties);
+        }
+    }
+
+    Ok(TiledTileset { name, first_gid, tile_count, tile_size, image, layout, animations, properties })
+}
+
+// collect every layer under `parent` into `layers`, flattening groups.
+fn parse_layers(parent: Node,
+                offset: Vec2,
+                visible: bool,
+                size: UVec2,
+                layers: &mut Vec<TiledLayer>)
+                -> Result<(), TiledLoaderError>
+{
+    for node in parent.children().filter(Node::is_element) {
+        let name = node.attribute("name").unwrap_or_default().to_owned();
+        let offset = offset + Vec2::new(attr_or(node, "offsetx", 0.)?, attr_or(node, "offsety", 0.)?);
+        let visible = visible && attr_or(node, "visible", 1)? != 0;
+        let properties = parse_properties(node);
+
+        match node.tag_name().name() {
+            "layer" => {
+                let data = children(node, "data").next().ok_or_else(|| invalid(&format!("layer {name} has no <data>")))?;
+                let tiles = parse_data(data, (size.x * size.y) as usize)?;
+                layers.push(TiledLayer::Tiles(TiledTileLayer { name, offset, visible, tiles, properties }));
+            }
+            "objectgroup" => {
+                let objects = children(node, "object").map(parse_object).collect::<Result<_, _>>()?;
+                layers.push(TiledLayer::Objects(TiledObjectLayer { name, offset, visible, objects, properties }));
+            }
+            "group" => parse_layers(node, offset, visible, size, layers)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// the gids in a tile layer's <data>.
+fn parse_data(data: Node, count: usize) -> Result<Vec<u32>, TiledLoaderError>
+{
+    if children(data, "chunk").next().is_some() {
+        return Err(TiledLoaderError::Unsupported("infinite maps".into()));
+    }
+    let text = data.text().unwrap_or_default();
+
+    let tiles = match data.attribute("encoding") {
+        Some("csv") => text.split(',')
+                           .map(str::trim)
+                           .filter(|s| !s.is_empty())
+                           .map(|s| s.parse().map_err(|_| invalid(&format!("bad tile in csv data: {s}"))))
+                           .collect::<Result<Vec<u32>, _>>()?,
+        Some("base64") => {
+            let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+            let bytes = base64::engine::general_purpose::STANDARD.decode(text)
+                                                                 .map_err(|e| invalid(&format!("bad base64 data: {e}")))?;
+            let bytes = match data.attribute("compression") {
+                None | Some("") => bytes,
+                Some("zlib") => inflate(flate2::read::ZlibDecoder::new(&bytes[..]))?,
+                Some("gzip") => inflate(flate2::read::GzDecoder::new(&bytes[..]))?,
+                Some(other) => return Err(TiledLoaderError::Unsupported(format!("{other} compression"))),
+            };
+            bytes.chunks_exact(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+        }
+        None => children(data, "tile").map(|tile| attr_or(tile, "gid", 0)).collect::<Result<_, _>>()?,
+        Some(other) => return Err(TiledLoaderError::Unsupported(format!("{other} encoding"))),
+    };
+
+    if tiles.len() != count {
+        return Err(invalid(&format!("expected {count} tiles in layer, found {}", tiles.len())));
+    }
+    Ok(tiles)
+}
+
+fn inflate(mut decoder: impl Read) -> Result<Vec<u8>, TiledLoaderError>
+{
+    let mut bytes = Vec::new();
+    decoder.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn parse_object(node: Node) -> Result<TiledObject, TiledLoaderError>
+{
+    Ok(TiledObject { id:         attr_or(node, "id", 0)?,
+                     name:       node.attribute("name").unwrap_or_default().to_owned(),
+                     class:      node.attribute("class").or(node.attribute("type")).unwrap_or_default().to_owned(),
+                     position:   Vec2::new(attr_or(node, "x", 0.)?, attr_or(node, "y", 0.)?),
+                     size:       Vec2::new(attr_or(node, "width", 0.)?, attr_or(node, "height", 0.)?),
+                     rotation:   attr_or(node, "rotation", 0.)?,
+                     gid:        node.attribute("gid").map(|_| attr(node, "gid")).transpose()?,
+                     visible:    attr_or(node, "visible", 1)? != 0,
+                     properties: parse_properties(node) })
+}
+
+// the custom properties directly on `node`. Values that don't parse as their
+// declared type are kept as strings.
+fn parse_properties(node: Node) -> TiledProperties
+{
+    let mut properties = TiledProperties::default();
+    let Some(list) = children(node, "properties").next() else { return properties };
+
+    for property in children(list, "property") {
+        let Some(name) = property.attribute("name") else { continue };
+        let value = property.attribute("value").or(property.text()).unwrap_or_default();
+        let parsed = match property.attribute("type").unwrap_or("string") {
+            "bool" => value.parse().ok().map(TiledProperty::Bool),
+            "int" => value.parse().ok().map(TiledProperty::Int),
+            "float" => value.parse().ok().map(TiledProperty::Float),
+            _ => None,
+        };
+        properties.insert(name.to_owned(), parsed.unwrap_or_else(|| TiledProperty::String(value.to_owned())));
+    }
+    properties
+}
+
+
+/// Spawns a [`Sprite3dTiledMap`] as children of this entity once it (and its
+/// images) have loaded. The map's top-left corner is at the entity's origin.
+///
+/// - tile layers become a [`Sprite3dTilemap`] for each tileset they use, or
+///   a `Sprite3d` per tile if `merge` is off.
+/// - each layer sits `layer_spacing` further along the plane's normal than the
+///   one below it, unless it has a `height` property (in metres) to say where.
+///   Layer offsets move it within the plane.
+/// - objects become entities with a [`TiledObject`] component. Tile objects
+///   also get a `Sprite3d`, standing upright on `XZ` maps.
+///
+/// Invisible layers and objects are skipped.
+#[derive(Component, Clone, Debug)]
+#[require(Transform, Visibility)]
+pub struct Sprite3dTiled
+{
+    pub map:              Handle<Sprite3dTiledMap>,
+
+    /// Pixels in the map per world-space metre.
+    pub pixels_per_metre: f32,
+    pub plane:            TilemapPlane,
+
+    /// The distance between layers along the plane's normal.
+    pub layer_spacing:    f32,
+
+    /// Merge tile layers into `Sprite3dTilemap` chunks (default), rather
+    /// than spawning a `Sprite3d` per tile.
+    pub merge:            bool,
+
+    spawned:              bool,
+}
+
+impl Sprite3dTiled
+{
+    pub fn new(map: Handle<Sprite3dTiledMap>, pixels_per_metre: f32) -> Self
+    {
+        Self { map, pixels_per_metre, plane: TilemapPlane::XZ, layer_spacing: 0.01, merge: true, spawned: false }
+    }
+
+    pub fn with_plane(mut self, plane: TilemapPlane) -> Self
+    {
+        self.plane = plane;
+        self
+    }
+
+    pub fn with_layer_spacing(mut self, layer_spacing: f32) -> Self
+    {
+        self.layer_spacing = layer_spacing;
+        self
+    }
+
+    pub fn with_merge(mut self, merge: bool) -> Self
+    {
+        self.merge = merge;
+        self
+    }
+
+    /// Whether the map has been spawned yet.
+    pub fn is_spawned(&self) -> bool { self.spawned }
+
+    // a position in map pixels, `height` along the normal, to local space.
+    fn to_local(&self, pixels: Vec2, height: f32) -> Vec3
+    {
+        let (right, down, normal) = self.plane.axes();
+        let p = pixels / self.pixels_per_metre;
+        right * p.x + down * p.y + normal * height
+    }
+
+    // rotates a +Z facing quad to lie flat in the plane.
+    fn flat(&self) -> Quat
+    {
+        match self.plane {
+            TilemapPlane::XZ => Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2),
+            TilemapPlane::XY => Quat::IDENTITY,
+        }
+    }
+}
+
+fn layer_height(layer: &TiledLayer, index: usize, spacing: f32) -> f32
+{
+    match layer.properties().get("height") {
+        Some(TiledProperty::Float(h)) => *h as f32,
+        Some(TiledProperty::Int(h)) => *h as f32,
+        _ => index as f32 * spacing,
+    }
+}
+
+// Spawn the contents of each `Sprite3dTiled` once its map has loaded.
+pub(crate) fn spawn_tiled_maps(mut commands: Commands,
+                               asset_server: Res<AssetServer>,
+                               maps: Res<Assets<Sprite3dTiledMap>>,
+                               mut query: Query<(Entity, &mut Sprite3dTiled)>)
+{
+    for (entity, mut tiled) in query.iter_mut() {
+        if tiled.spawned || !asset_server.is_loaded_with_dependencies(&tiled.map) {
+            continue;
+        }
+        let Some(map) = maps.get(&tiled.map) else { continue };
+        tiled.spawned = true;
+
+        let tile_world = map.tile_size.as_vec2() / tiled.pixels_per_metre;
+
+        for (i, layer) in map.layers.iter().enumerate() {
+            let height = layer_height(layer, i, tiled.layer_spacing);
+            match layer {
+                TiledLayer::Tiles(layer) if layer.visible => {
+                    let origin = tiled.to_local(layer.offset, height);
+                    if tiled.merge {
+                        spawn_merged_layer(&mut commands, entity, map, layer, origin, tile_world, tiled.plane);
+                    } else {
+                        spawn_tile_sprites(&mut commands, entity, map, layer, &tiled, origin, tile_world);
+                    }
+                }
+                TiledLayer::Objects(layer) if layer.visible => {
+                    for object in layer.objects.iter().filter(|o| o.visible) {
+                        spawn_object(&mut commands, entity, map, object, &tiled, layer.offset, height);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// one `Sprite3dTilemap` per tileset the layer uses.
+fn spawn_merged_layer(commands: &mut Commands,
+                      parent: Entity,
+                      map: &Sprite3dTiledMap,
+                      layer: &TiledTileLayer,
+                      origin: Vec3,
+                      tile_world: Vec2,
+                      plane: TilemapPlane)
+{
+    for tileset in &map.tilesets {
+        if !layer.tiles.iter().any(|&gid| tileset.tile_id(gid).is_some()) {
+            continue;
+        }
+        let mut tilemap = Sprite3dTilemap::new(tileset.image.clone(), tileset.layout.clone(), map.size, tile_world)
+            .with_plane(plane)
+            .with_tiles(|pos| tileset.tile_id(layer.tiles[(pos.y * map.size.x + pos.x) as usize]));
+        tilemap.animations = tileset.animations.clone();
+
+        commands.spawn((Name::new(format!("{} ({})", layer.name, tileset.name)),
+                        tilemap,
+                        Transform::from_translation(origin),
+                        ChildOf(parent)));
+    }
+}
+
+fn spawn_tile_sprites(commands: &mut Commands,
+                      parent: Entity,
+                      map: &Sprite3dTiledMap,
+                      layer: &TiledTileLayer,
+                      tiled: &Sprite3dTiled,
+                      origin: Vec3,
+                      tile_world: Vec2)
+{
+    for (i, &gid) in layer.tiles.iter().enumerate() {
+        let Some(tileset) = map.tileset(gid) else { continue };
+        let index = tileset.tile_id(gid).unwrap();
+        let pos = UVec2::new(i as u32 % map.size.x, i as u32 / map.size.x);
+
+        // tiles are anchored to the bottom-left of their cell, since tilesets
+        // can have bigger tiles than the map's grid.
+        let corner = Vec2::new(pos.x as f32, pos.y as f32 + 1.) * tile_world * tiled.pixels_per_metre;
+        let sprite = Sprite { image: tileset.image.clone(),
+                              texture_atlas: Some(TextureAtlas { layout: tileset.layout.clone(), index }),
+                              flip_x: gid & FLIP_X != 0,
+                              flip_y: gid & FLIP_Y != 0,
+                              ..default() };
+        let mut entity = commands.spawn((sprite,
+                                         Sprite3d { pixels_per_metre: tiled.pixels_per_metre,
+                                                    pivot: Some(Vec2::ZERO),
+                                                    ..default() },
+                                         Transform::from_translation(origin + tiled.to_local(corner, 0.))
+                                             .with_rotation(tiled.flat()),
+                                         ChildOf(parent)));
+        if let Some(animation) = tileset.animations.get(&index) {
+            entity.insert(Sprite3dAnimation::from_clip(tile_clip(animation)));
+        }
+    }
+}
+
+fn spawn_object(commands: &mut Commands,
+                parent: Entity,
+                map: &Sprite3dTiledMap,
+                object: &TiledObject,
+                tiled: &Sprite3dTiled,
+                offset: Vec2,
+                height: f32)
+{
+    let translation = tiled.to_local(offset + object.position, height);
+    // tiled rotates clockwise, about the object's position.
+    let rotation = match tiled.plane {
+        TilemapPlane::XZ => Quat::from_rotation_y(-object.rotation.to_radians()),
+        TilemapPlane::XY => Quat::from_rotation_z(-object.rotation.to_radians()),
+    };
+    let mut entity = commands.spawn((Name::new(object.name.clone()),
+                                     object.clone(),
+                                     Transform::from_translation(translation).with_rotation(rotation),
+                                     ChildOf(parent)));
+
+    let Some(gid) = object.gid else { return };
+    let Some(tileset) = map.tileset(gid) else { return };
+    let index = tileset.tile_id(gid).unwrap();
+
+    // objects can be resized in the editor, which scales their tile.
+    let scale = match object.size {
+        Vec2::ZERO => Vec2::ONE,
+        size => size / tileset.tile_size.as_vec2(),
+    };
+    let sprite = Sprite { image: tileset.image.clone(),
+                          texture_atlas: Some(TextureAtlas { layout: tileset.layout.clone(), index }),
+                          flip_x: gid & FLIP_X != 0,
+                          flip_y: gid & FLIP_Y != 0,
+                          ..default() };
+    entity.insert((sprite,
+                   Sprite3d { pixels_per_metre: tiled.pixels_per_metre, pivot: Some(Vec2::ZERO), ..default() },
+                   Transform::from_translation(translation).with_rotation(rotation).with_scale(scale.extend(1.))));
+    if let Some(animation) = tileset.animations.get(&index) {
+        entity.insert(Sprite3dAnimation::from_clip(tile_clip(animation)));
+    }
+}
+
+// a tileset animation, played on a single sprite.
+fn tile_clip(animation: &TileAnimation) -> Sprite3dClip
+{
+    Sprite3dClip::from_frames("tile", animation.frames.iter().enumerate().map(|(i, &f)| (f, animation.frame_duration(i))))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // the gids of a 2x2 layer: tiles 1 and 2, an empty tile, and tile 1
+    // flipped horizontally.
+    const GIDS: [u32; 4] = [1, 2, 0, FLIP_X | 1];
+
+    fn layers(tmx: &str) -> Result<Vec<TiledLayer>, TiledLoaderError>
+    {
+        let doc = roxmltree::Document::parse(tmx)?;
+        let mut layers = Vec::new();
+        parse_layers(doc.root_element(), Vec2::ZERO, true, UVec2::splat(2), &mut layers)?;
+        Ok(layers)
+    }
+
+    fn tiles(data: &str) -> Vec<u32>
+    {
+        let tmx = format!(r#"<map><layer name="ground" width="2" height="2">{data}</layer></map>"#);
+        match layers(&tmx).unwrap().remove(0) {
+            TiledLayer::Tiles(layer) => layer.tiles,
+            TiledLayer::Objects(_) => panic!("expected a tile layer"),
+        }
+    }
+
+    #[test]
+    fn xml_data()
+    {
+        let data = r#"<data><tile gid="1"/><tile gid="2"/><tile/><tile gid="2147483649"/></data>"#;
+        assert_eq!(tiles(data), GIDS);
+    }
+
+    #[test]
+    fn csv_data()
+    {
+        assert_eq!(tiles("<data encoding=\"csv\">\n1,2,\n0,2147483649\n</data>"), GIDS);
+    }
+
+    #[test]
+    fn base64_data()
+    {
+        assert_eq!(tiles(r#"<data encoding="base64"> AQAAAAIAAAAAAAAAAQAAgA== </data>"#), GIDS);
+    }
+
+    #[test]
+    fn zlib_data()
+    {
+        assert_eq!(tiles(r#"<data encoding="base64" compression="zlib">eJxjZGBgYGKAAEYGhgYAALwAhQ==</data>"#), GIDS);
+    }
+
+    #[test]
+    fn gzip_data()
+    {
+        assert_eq!(tiles(r#"<data encoding="base64" compression="gzip">H4sIAAAAAAACA2NkYGBgYoAARgaGBgAeoOz7EAAAAA==</data>"#),
+                   GIDS);
+    }
+
+    #[test]
+    fn bad_data()
+    {
+        let wrong_count = r#"<map><layer><data encoding="csv">1,2,3</data></layer></map>"#;
+        assert!(matches!(layers(wrong_count), Err(TiledLoaderError::Invalid(_))));
+        let zstd = r#"<map><layer><data encoding="base64" compression="zstd">AAAA</data></layer></map>"#;
+        assert!(matches!(layers(zstd), Err(TiledLoaderError::Unsupported(_))));
+    }
+
+    #[test]
+    fn groups_offset_and_hide_their_layers()
+    {
+        let tmx = r#"<map>
+                       <group offsetx="10" visible="0">
+                         <objectgroup name="spawns" offsety="5">
+                           <object id="3" name="player" x="16" y="32"/>
+                         </objectgroup>
+                       </group>
+                     </map>"#;
+        let TiledLayer::Objects(layer) = layers(tmx).unwrap().remove(0) else { panic!("expected an object layer") };
+        assert_eq!(layer.offset, Vec2::new(10., 5.));
+        assert!(!layer.visible);
+        assert_eq!(layer.objects[0].name, "player");
+        assert_eq!(layer.objects[0].position, Vec2::new(16., 32.));
+    }
+}
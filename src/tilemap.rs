@@ -0,0 +1,725 @@
+use bevy::asset::RenderAssetUsages;
+use bevy::mesh::{Indices, PrimitiveTopology, VertexAttributeValues};
+use bevy::platform::collections::{HashMap, HashSet};
+use bevy::prelude::*;
+
+use crate::{Sprite3dCaches, TileGrid, DEFAULT_ALPHA_MODE};
+
+/// The plane a [`Sprite3dTilemap`] is laid out in, relative to its transform.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TilemapPlane
+{
+    /// A floor facing `+Y`. Columns run along `+X` and rows along `+Z`, so the
+    /// top of each tile's image points towards `-Z`.
+    #[default]
+    XZ,
+    /// A wall facing `+Z`. Columns run along `+X` and rows down along `-Y`,
+    /// so the map reads the same way it would in a 2d editor.
+    XY,
+}
+
+impl TilemapPlane
+{
+    // world-space axes for a tile's right, down and normal directions.
+    pub(crate) fn axes(self) -> (Vec3, Vec3, Vec3)
+    {
+        match self {
+            TilemapPlane::XZ => (Vec3::X, Vec3::Z, Vec3::Y),
+            TilemapPlane::XY => (Vec3::X, Vec3::NEG_Y, Vec3::Z),
+        }
+    }
+}
+
+/// A looping animation for every tile of one type in a [`Sprite3dTilemap`],
+/// eg. water or lava. All tiles of that type animate in sync.
+#[derive(Clone, Debug)]
+pub struct TileAnimation
+{
+    /// The atlas indices to cycle through.
+    pub frames:    Vec<usize>,
+
+    /// Time each frame is shown for, in seconds. As with `Sprite3dClip`, the
+    /// last duration is reused if there are fewer durations than frames.
+    pub durations: Vec<f32>,
+}
+
+impl TileAnimation
+{
+    /// An animation where every frame is shown for `frame_duration` seconds.
+    pub fn new(frames: Vec<usize>, frame_duration: f32) -> Self { Self { frames, durations: vec![frame_duration] } }
+
+    /// An animation built from `(atlas index, seconds)` pairs.
+    pub fn from_frames(frames: impl IntoIterator<Item = (usize, f32)>) -> Self
+    {
+        let (frames, durations) = frames.into_iter().unzip();
+        Self { frames, durations }
+    }
+
+    pub(crate) fn frame_duration(&self, frame: usize) -> f32
+    {
+        self.durations.get(frame).or(self.durations.last()).copied().unwrap_or(0.)
+    }
+
+    // which frame is showing `time` seconds into the animation.
+    fn frame_at(&self, time: f32) -> usize
+    {
+        let total: f32 = (0..self.frames.len()).map(|i| self.frame_duration(i)).sum();
+        if total <= 0. {
+            return 0;
+        }
+        let mut time = time.rem_euclid(total);
+        for i in 0..self.frames.len() {
+            time -= self.frame_duration(i);
+            if time < 0. {
+                return i;
+            }
+        }
+        self.frames.len() - 1
+    }
+}
+
+/// How a [`Sprite3dTilemap`] streams its chunks in and out around
+/// [`Sprite3dStreamFocus`] entities, for maps too big to build all at once.
+///
+/// Chunks load nearest first, at most `budget` per frame, so moving into a
+/// new area spreads the work over a few frames. Each load and unload triggers
+/// [`Sprite3dChunkLoaded`] / [`Sprite3dChunkUnloaded`] on the tilemap.
+/// Without any focus entities, nothing is loaded.
+#[derive(Clone, Copy, Debug)]
+pub struct TilemapStreaming
+{
+    /// Chunks whose centre is within this distance of a focus are loaded, in
+    /// the tilemap's local space.
+    pub load_radius:   f32,
+
+    /// Loaded chunks further than this from every focus are unloaded. Should
+    /// be a bit more than `load_radius`, so chunks on the edge don't flicker.
+    pub unload_radius: f32,
+
+    /// The most chunks to build per frame.
+    pub budget:        usize,
+}
+
+impl TilemapStreaming
+{
+    /// Streaming within `load_radius`, unloading at `1.25 * load_radius` and
+    /// building up to 4 chunks per frame.
+    pub fn new(load_radius: f32) -> Self { Self { load_radius, unload_radius: load_radius * 1.25, budget: 4 } }
+
+    pub fn with_unload_radius(mut self, unload_radius: f32) -> Self
+    {
+        self.unload_radius = unload_radius;
+        self
+    }
+
+    pub fn with_budget(mut self, budget: usize) -> Self
+    {
+        self.budget = budget;
+        self
+    }
+}
+
+/// A grid of atlas tiles, merged into one mesh per chunk rather than spawned
+/// as a `Sprite3d` each. Large maps then cost a handful of entities and draw
+/// calls instead of thousands.
+///
+/// Chunks are spawned as children of the tilemap's entity, once its image and
+/// layout have loaded. On square grids, tile `(0, 0)` has its top-left
+/// corner at the tilemap's origin. Materials are shared with regular sprites
+/// through [`Sprite3dCaches`].
+#[derive(Component, Clone, Debug)]
+#[require(Transform, Visibility)]
+pub struct Sprite3dTilemap
+{
+    pub image:       Handle<Image>,
+    pub layout:      Handle<TextureAtlasLayout>,
+
+    /// The size of the map, in tiles.
+    pub size:        UVec2,
+
+    /// The world-space size of a single tile.
+    pub tile_size:   Vec2,
+
+    pub plane:       TilemapPlane,
+
+    /// Square (default), hex or isometric.
+    pub grid:        TileGrid,
+
+    /// How far each tile is raised along the plane's normal per tile it is
+    /// down the screen (see [`TileGrid::sort_depth`]), so that tiles which
+    /// overlap, as on hex and isometric grids, are drawn in the right order.
+    /// Tiles are also ordered within each chunk's mesh, for
+    /// `AlphaMode::Blend`. `0` by default.
+    pub sort_offset: f32,
+
+    /// The size of each merged mesh, in tiles. `32x32` by default.
+    pub chunk_size:  UVec2,
+
+    /// See `Sprite3d::alpha_mode`.
+    pub alpha_mode:  AlphaMode,
+
+    /// See `Sprite3d::unlit`.
+    pub unlit:       bool,
+
+    /// See `Sprite3d::emissive`.
+    pub emissive:    LinearRgba,
+
+    /// Animations for tiles, keyed by the atlas index they're placed with.
+    /// Animating only rewrites the UVs of those tiles within their chunk's
+    /// mesh, rather than rebuilding it.
+    pub animations:  HashMap<usize, TileAnimation>,
+
+    /// Only build the chunks near [`Sprite3dStreamFocus`] entities. `None`
+    /// (default) builds every chunk.
+    pub streaming:   Option<TilemapStreaming>,
+
+    tiles:           Vec<Option<usize>>,
+    chunks:          HashMap<UVec2, Entity>,
+    // chunks edited since they were last built.
+    dirty:           HashSet<UVec2>,
+    built:           bool,
+    // per chunk, the first vertex and type of each animated tile.
+    animated:        HashMap<UVec2, Vec<(usize, usize)>>,
+    // the frame each animation is currently showing.
+    anim_frames:     HashMap<usize, usize>,
+    // chunks currently streamed in, including empty ones.
+    loaded:          HashSet<UVec2>,
+    // meshes of unloaded chunks, reused for the next ones loaded. At most
+    // `budget` are kept.
+    spare_meshes:    Vec<Handle<Mesh>>,
+}
+
+impl Sprite3dTilemap
+{
+    /// An empty tilemap of `size` tiles.
+    pub fn new(image: Handle<Image>, layout: Handle<TextureAtlasLayout>, size: UVec2, tile_size: Vec2) -> Self
+    {
+        Self { image,
+               layout,
+               size,
+               tile_size,
+               plane: TilemapPlane::XZ,
+               grid: TileGrid::Square,
+               sort_offset: 0.,
+               chunk_size: UVec2::splat(32),
+               alpha_mode: DEFAULT_ALPHA_MODE,
+               unlit: false,
+               emissive: LinearRgba::BLACK,
+               animations: HashMap::default(),
+               tiles: vec![None; (size.x * size.y) as usize],
+               chunks: HashMap::default(),
+               dirty: HashSet::default(),
+               built: false,
+               animated: HashMap::default(),
+               anim_frames: HashMap::default(),
+               streaming: None,
+               loaded: HashSet::default(),
+               spare_meshes: Vec::new() }
+    }
+
+    /// Fills every tile with the atlas index returned by `tile`, or leaves it
+    /// empty for `None`.
+    pub fn with_tiles(mut self, mut tile: impl FnMut(UVec2) -> Option<usize>) -> Self
+    {
+        for y in 0..self.size.y {
+            for x in 0..self.size.x {
+                let pos = UVec2::new(x, y);
+                let i = self.tile_index(pos).unwrap();
+                self.tiles[i] = tile(pos);
+            }
+        }
+        self
+    }
+
+    pub fn with_plane(mut self, plane: TilemapPlane) -> Self
+    {
+        self.plane = plane;
+        self
+    }
+
+    /// Lays the map out on a hex or isometric grid, raising each row by
+    /// `sort_offset` to keep overlapping tiles in order.
+    pub fn with_grid(mut self, grid: TileGrid, sort_offset: f32) -> Self
+    {
+        self.grid = grid;
+        self.sort_offset = sort_offset;
+        self
+    }
+
+    /// Animates every tile placed as atlas index `index`.
+    pub fn with_animation(mut self, index: usize, animation: TileAnimation) -> Self
+    {
+        self.animations.insert(index, animation);
+        self
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: UVec2) -> Self
+    {
+        self.chunk_size = chunk_size.max(UVec2::ONE);
+        self
+    }
+
+    pub fn with_streaming(mut self, streaming: TilemapStreaming) -> Self
+    {
+        self.streaming = Some(streaming);
+        self
+    }
+
+    /// Places atlas index `index` at `pos`, returning the tile that was there.
+    ///
+    /// Only the chunk containing `pos` is rebuilt, later in the frame, so any
+    /// number of edits made in one frame cost one rebuild per edited chunk.
+    /// Does nothing if `pos` is outside the map.
+    pub fn set_tile(&mut self, pos: UVec2, index: usize) -> Option<usize> { self.replace_tile(pos, Some(index)) }
+
+    /// Removes the tile at `pos`, returning it. See [`Self::set_tile`].
+    pub fn clear_tile(&mut self, pos: UVec2) -> Option<usize> { self.replace_tile(pos, None) }
+
+    fn replace_tile(&mut self, pos: UVec2, tile: Option<usize>) -> Option<usize>
+    {
+        let i = self.tile_index(pos)?;
+        let old = std::mem::replace(&mut self.tiles[i], tile);
+        if old != tile {
+            let chunk = self.chunk_of(pos);
+            self.dirty.insert(chunk);
+        }
+        old
+    }
+
+    /// The atlas index at `pos`, if there's a tile there.
+    pub fn tile(&self, pos: UVec2) -> Option<usize> { self.tile_index(pos).and_then(|i| self.tiles[i]) }
+
+    /// The number of chunks along each axis.
+    pub fn chunk_count(&self) -> UVec2 { (self.size + self.chunk_size - 1) / self.chunk_size }
+
+    /// The chunk containing tile `pos`.
+    pub fn chunk_of(&self, pos: UVec2) -> UVec2 { pos / self.chunk_size }
+
+    /// The entity holding the mesh for chunk `chunk`, once it's been spawned.
+    /// Chunks without any tiles aren't spawned.
+    pub fn chunk_entity(&self, chunk: UVec2) -> Option<Entity> { self.chunks.get(&chunk).copied() }
+
+    /// The centre of tile `pos`, relative to the tilemap's transform.
+    pub fn tile_to_local(&self, pos: UVec2) -> Vec3
+    {
+        let (right, down, normal) = self.plane.axes();
+        let centre = self.grid.tile_to_plane(pos.as_ivec2(), self.tile_size);
+        let depth = self.grid.sort_depth(pos.as_ivec2(), self.tile_size);
+        right * centre.x + down * centre.y + normal * depth * self.sort_offset
+    }
+
+    /// The tile under `local`, a point relative to the tilemap's transform,
+    /// if it's within the map.
+    pub fn local_to_tile(&self, local: Vec3) -> Option<UVec2>
+    {
+        let (right, down, _) = self.plane.axes();
+        let pos = self.grid.plane_to_tile(Vec2::new(local.dot(right), local.dot(down)), self.tile_size);
+        (pos.cmpge(IVec2::ZERO).all() && pos.cmplt(self.size.as_ivec2()).all()).then(|| pos.as_uvec2())
+    }
+
+    /// Whether chunk `chunk` is built. Always `true` once built, for tilemaps
+    /// that aren't streaming.
+    pub fn is_chunk_loaded(&self, chunk: UVec2) -> bool
+    {
+        match self.streaming {
+            Some(_) => self.loaded.contains(&chunk),
+            None => self.built && chunk.cmplt(self.chunk_count()).all(),
+        }
+    }
+
+    // distance from the centre of `chunk` to the nearest focus, in local space.
+    fn chunk_distance(&self, chunk: UVec2, focus: &[Vec3]) -> f32
+    {
+        let min = chunk * self.chunk_size;
+        let max = (min + self.chunk_size).min(self.size) - 1;
+        let centre = (self.tile_to_local(min) + self.tile_to_local(max)) / 2.;
+        focus.iter().map(|f| f.distance(centre)).fold(f32::INFINITY, f32::min)
+    }
+
+    // chunks with their centre within `radius` of any focus.
+    fn chunks_near(&self, focus: &[Vec3], radius: f32) -> HashSet<UVec2>
+    {
+        let (right, down, _) = self.plane.axes();
+        let count = self.chunk_count().as_ivec2();
+        // tiles are at least half their size apart on any grid.
+        let reach = (radius / (self.tile_size.min_element() / 2.) / self.chunk_size.min_element() as f32).ceil() as i32 + 1;
+
+        let mut near = HashSet::default();
+        for f in focus {
+            let tile = self.grid.plane_to_tile(Vec2::new(f.dot(right), f.dot(down)), self.tile_size);
+            let centre = tile.div_euclid(self.chunk_size.as_ivec2());
+            let min = (centre - reach).max(IVec2::ZERO);
+            let max = (centre + reach).min(count - 1);
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let chunk = UVec2::new(x as u32, y as u32);
+                    if self.chunk_distance(chunk, std::slice::from_ref(f)) <= radius {
+                        near.insert(chunk);
+                    }
+                }
+            }
+        }
+        near
+    }
+
+    // where a chunk's mesh is placed. Vertices are relative to this, so that
+    // transparent chunks are sorted by where they actually are.
+    fn chunk_origin(&self, chunk: UVec2) -> Vec3 { self.tile_to_local(chunk * self.chunk_size) }
+
+    fn tile_index(&self, pos: UVec2) -> Option<usize>
+    {
+        (pos.x < self.size.x && pos.y < self.size.y).then(|| (pos.y * self.size.x + pos.x) as usize)
+    }
+
+    // the atlas index to draw for a tile, taking animations into account.
+    fn displayed(&self, index: usize) -> usize
+    {
+        match (self.animations.get(&index), self.anim_frames.get(&index)) {
+            (Some(animation), Some(&frame)) => animation.frames.get(frame).copied().unwrap_or(index),
+            _ => index,
+        }
+    }
+
+    // build the merged mesh for every tile in `chunk`, or `None` if the chunk
+    // is empty. Also returns where the chunk's animated tiles are in the mesh.
+    fn chunk_mesh(&self,
+                  chunk: UVec2,
+                  layout: &TextureAtlasLayout,
+                  image_size: Vec2)
+                  -> Option<(Mesh, Vec<(usize, usize)>)>
+    {
+        let (right, down, normal) = self.plane.axes();
+        let min = chunk * self.chunk_size;
+        let max = (min + self.chunk_size).min(self.size);
+
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut indices = Vec::new();
+        let mut animated = Vec::new();
+
+        // draw tiles further down the screen last, so they overlap the ones
+        // above them.
+        let mut cells: Vec<UVec2> = (min.y..max.y).flat_map(|y| (min.x..max.x).map(move |x| UVec2::new(x, y))).collect();
+        if self.grid != TileGrid::Square {
+            cells.sort_by(|a, b| {
+                let depth = |p: &UVec2| self.grid.sort_depth(p.as_ivec2(), self.tile_size);
+                depth(a).total_cmp(&depth(b))
+            });
+        }
+        let origin = self.chunk_origin(chunk);
+
+        for pos in cells {
+            let Some(index) = self.tile(pos) else { continue };
+            let Some(rect) = layout.textures.get(self.displayed(index)) else { continue };
+
+            if self.animations.contains_key(&index) {
+                animated.push((positions.len(), index));
+            }
+
+            let centre = self.tile_to_local(pos) - origin;
+            let corner = |dx: f32, dy: f32| {
+                let p = Vec2::new(dx - 0.5, dy - 0.5) * self.tile_size;
+                (centre + right * p.x + down * p.y).to_array()
+            };
+
+            let base = positions.len() as u32;
+            // bottom-left, bottom-right, top-right, top-left
+            positions.extend([corner(0., 1.), corner(1., 1.), corner(1., 0.), corner(0., 0.)]);
+            uvs.extend(tile_uvs(*rect, image_size));
+            indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+
+        if positions.is_empty() {
+            return None;
+        }
+        let normals = vec![normal.to_array(); positions.len()];
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default());
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.insert_indices(Indices::U32(indices));
+        Some((mesh, animated))
+    }
+}
+
+// UVs for a tile's four corners, in the same order as its vertices.
+pub(crate) fn tile_uvs(rect: URect, image_size: Vec2) -> [[f32; 2]; 4]
+{
+    let uv_min = rect.min.as_vec2() / image_size;
+    let uv_max = rect.max.as_vec2() / image_size;
+    [[uv_min.x, uv_max.y], [uv_max.x, uv_max.y], [uv_max.x, uv_min.y], [uv_min.x, uv_min.y]]
+}
+
+/// Marks one of the merged meshes of a [`Sprite3dTilemap`].
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Sprite3dTilemapChunk
+{
+    /// The chunk's position in the map, in chunks.
+    pub chunk: UVec2,
+}
+
+/// Marks an entity (usually the player or camera) that streaming tilemaps
+/// load their chunks around. See [`TilemapStreaming`].
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct Sprite3dStreamFocus;
+
+/// Triggered on a streaming tilemap when one of its chunks is loaded.
+#[derive(EntityEvent, Clone, Debug)]
+pub struct Sprite3dChunkLoaded
+{
+    pub entity:       Entity,
+    pub chunk:        UVec2,
+    /// The chunk's mesh entity, or `None` if the chunk has no tiles.
+    pub chunk_entity: Option<Entity>,
+}
+
+/// Triggered on a streaming tilemap when one of its chunks is unloaded.
+#[derive(EntityEvent, Clone, Debug)]
+pub struct Sprite3dChunkUnloaded
+{
+    pub entity: Entity,
+    pub chunk:  UVec2,
+}
+
+// Build the chunk meshes for new tilemaps, and rebuild the chunks of existing
+// ones that have been edited since last frame. Chunks that gain their first
+// tile are spawned, and ones that lose their last are despawned. Streaming
+// tilemaps also load and unload chunks around their focus entities.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn build_tilemaps(mut commands: Commands,
+                             images: Res<Assets<Image>>,
+                             atlas_layouts: Res<Assets<TextureAtlasLayout>>,
+                             mut caches: ResMut<Sprite3dCaches>,
+                             mut meshes: ResMut<Assets<Mesh>>,
+                             mut materials: ResMut<Assets<StandardMaterial>>,
+                             mut query: Query<(Entity, &mut Sprite3dTilemap, &GlobalTransform)>,
+                             focuses: Query<&GlobalTransform, With<Sprite3dStreamFocus>>,
+                             chunks: Query<&Mesh3d, With<Sprite3dTilemapChunk>>)
+{
+    for (entity, mut map, global) in query.iter_mut() {
+        if map.built && map.dirty.is_empty() && map.streaming.is_none() {
+            continue;
+        }
+        // wait for the assets, rather than building a map with no tiles.
+        let (Some(image), Some(layout)) = (images.get(&map.image), atlas_layouts.get(&map.layout)) else {
+            continue;
+        };
+        let image_size = image.size().as_vec2();
+        let material = caches.material(&mut materials, &map.image, map.alpha_mode, map.unlit, map.emissive);
+
+        let mut builds: Vec<UVec2>;
+        let mut loads = Vec::new();
+        match map.streaming {
+            None => {
+                if !map.built {
+                    let count = map.chunk_count();
+                    map.dirty.extend((0..count.y).flat_map(|y| (0..count.x).map(move |x| UVec2::new(x, y))));
+                    map.built = true;
+                }
+                builds = map.dirty.drain().collect();
+            }
+            Some(streaming) => {
+                if !map.built {
+                    map.built = true;
+                }
+                let to_local = global.affine().inverse();
+                let focus: Vec<Vec3> = focuses.iter().map(|f| to_local.transform_point3(f.translation())).collect();
+
+                let far: Vec<UVec2> = map.loaded
+                                         .iter()
+                                         .filter(|&&c| map.chunk_distance(c, &focus) > streaming.unload_radius)
+                                         .copied()
+                                         .collect();
+                for chunk in far {
+                    map.loaded.remove(&chunk);
+                    map.animated.remove(&chunk);
+                    if let Some(id) = map.chunks.remove(&chunk) {
+                        // keep the mesh around for the next chunk that loads,
+                        // up to a frame's worth of loads.
+                        if let Ok(mesh) = chunks.get(id) {
+                            if map.spare_meshes.len() < streaming.budget {
+                                map.spare_meshes.push(mesh.0.clone());
+                            }
+                        }
+                        commands.entity(id).despawn();
+                    }
+                    commands.trigger(Sprite3dChunkUnloaded { entity, chunk });
+                }
+
+                // edits to unloaded chunks are picked up when they load. The
+                // map is only touched when there's work, so a still map isn't
+                // marked changed.
+                builds = Vec::new();
+                if !map.dirty.is_empty() {
+                    let dirty: Vec<UVec2> = map.dirty.drain().collect();
+                    builds = dirty.into_iter().filter(|c| map.loaded.contains(c)).collect();
+                }
+
+                // the nearest chunks first, up to the budget.
+                loads = map.chunks_near(&focus, streaming.load_radius)
+                           .into_iter()
+                           .filter(|c| !map.loaded.contains(c))
+                           .collect();
+                loads.sort_by(|a, b| map.chunk_distance(*a, &focus).total_cmp(&map.chunk_distance(*b, &focus)));
+                loads.truncate(streaming.budget);
+                if !loads.is_empty() {
+                    map.loaded.extend(loads.iter().copied());
+                    builds.extend(loads.iter().copied());
+                }
+            }
+        }
+
+        for chunk in builds {
+            let mesh = map.chunk_mesh(chunk, layout, image_size);
+            let existing = map.chunks.get(&chunk).copied();
+
+            match &mesh {
+                Some((_, animated)) if !animated.is_empty() => {
+                    map.animated.insert(chunk, animated.clone());
+                }
+                _ => {
+                    map.animated.remove(&chunk);
+                }
+            }
+
+            match (mesh.map(|(mesh, _)| mesh), existing) {
+                // rebuild the chunk's mesh in place.
+                (Some(mesh), Some(id)) => {
+                    if let Some(old) = chunks.get(id).ok().and_then(|h| meshes.get_mut(&h.0)) {
+                        *old = mesh;
+                    }
+                }
+                (Some(mesh), None) => {
+                    let handle = match map.spare_meshes.pop() {
+                        Some(handle) => {
+                            if let Some(old) = meshes.get_mut(&handle) {
+                                *old = mesh;
+                            }
+                            handle
+                        }
+                        None => meshes.add(mesh),
+                    };
+                    let id = commands.spawn((Sprite3dTilemapChunk { chunk },
+                                             Mesh3d(handle),
+                                             material.clone(),
+                                             Transform::from_translation(map.chunk_origin(chunk)),
+                                             ChildOf(entity)))
+                                     .id();
+                    map.chunks.insert(chunk, id);
+                }
+                (None, Some(id)) => {
+                    commands.entity(id).despawn();
+                    map.chunks.remove(&chunk);
+                }
+                (None, None) => {}
+            }
+        }
+
+        for chunk in loads {
+            let chunk_entity = map.chunks.get(&chunk).copied();
+            commands.trigger(Sprite3dChunkLoaded { entity, chunk, chunk_entity });
+        }
+    }
+}
+
+// Step tile animations, rewriting just the UVs of tiles whose frame changed.
+pub(crate) fn animate_tilemaps(time: Res<Time>,
+                               images: Res<Assets<Image>>,
+                               atlas_layouts: Res<Assets<TextureAtlasLayout>>,
+                               mut meshes: ResMut<Assets<Mesh>>,
+                               mut query: Query<&mut Sprite3dTilemap>,
+                               chunks: Query<&Mesh3d, With<Sprite3dTilemapChunk>>)
+{
+    let elapsed = time.elapsed_secs();
+
+    for mut map in query.iter_mut() {
+        if map.animations.is_empty() || !map.built {
+            continue;
+        }
+        let (Some(image), Some(layout)) = (images.get(&map.image), atlas_layouts.get(&map.layout)) else {
+            continue;
+        };
+        let image_size = image.size().as_vec2();
+
+        // which tile types changed frame since last time.
+        let changed: Vec<(usize, usize, URect)> = map.animations.iter().filter_map(|(&index, animation)| {
+            let frame = animation.frame_at(elapsed);
+            if map.anim_frames.get(&index) == Some(&frame) {
+                return None;
+            }
+            let rect = animation.frames.get(frame).and_then(|&i| layout.textures.get(i))?;
+            Some((index, frame, *rect))
+        }).collect();
+        if changed.is_empty() {
+            continue;
+        }
+        for &(index, frame, _) in &changed {
+            map.anim_frames.insert(index, frame);
+        }
+        let changed: HashMap<usize, URect> = changed.into_iter().map(|(index, _, rect)| (index, rect)).collect();
+
+        for (chunk, tiles) in map.animated.iter() {
+            if !tiles.iter().any(|(_, index)| changed.contains_key(index)) {
+                continue;
+            }
+            let Some(mesh) = map.chunks.get(chunk).and_then(|&id| chunks.get(id).ok()) else { continue };
+            let Some(mesh) = meshes.get_mut(&mesh.0) else { continue };
+            let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute_mut(Mesh::ATTRIBUTE_UV_0) else {
+                continue;
+            };
+            for (vertex, index) in tiles {
+                if let Some(&rect) = changed.get(index) {
+                    uvs[*vertex..*vertex + 4].copy_from_slice(&tile_uvs(rect, image_size));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use bevy::asset::RenderAssetUsages;
+    use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct MapChanged(bool);
+
+    fn record_changed(map: Single<Ref<Sprite3dTilemap>>, mut changed: ResMut<MapChanged>) { changed.0 = map.is_changed(); }
+
+    #[test]
+    fn still_streaming_map_is_not_changed()
+    {
+        let mut app = App::new();
+        app.init_resource::<Assets<Image>>()
+           .init_resource::<Assets<TextureAtlasLayout>>()
+           .init_resource::<Assets<Mesh>>()
+           .init_resource::<Assets<StandardMaterial>>()
+           .init_resource::<Sprite3dCaches>()
+           .init_resource::<MapChanged>()
+           .add_systems(Update, (build_tilemaps, record_changed).chain());
+
+        let image = Image::new_fill(Extent3d { width: 2, height: 2, depth_or_array_layers: 1 },
+                                    TextureDimension::D2,
+                                    &[255; 4],
+                                    TextureFormat::Rgba8UnormSrgb,
+                                    RenderAssetUsages::default());
+        let image = app.world_mut().resource_mut::<Assets<Image>>().add(image);
+        let layout = TextureAtlasLayout::from_grid(UVec2::ONE, 2, 2, None, None);
+        let layout = app.world_mut().resource_mut::<Assets<TextureAtlasLayout>>().add(layout);
+
+        let map = Sprite3dTilemap::new(image, layout, UVec2::splat(4), Vec2::ONE).with_tiles(|_| Some(0))
+                                                                                  .with_chunk_size(UVec2::splat(2))
+                                                                                  .with_streaming(TilemapStreaming::new(100.).with_budget(8));
+        app.world_mut().spawn(map);
+        app.world_mut().spawn((Sprite3dStreamFocus, GlobalTransform::default()));
+
+        app.update();
+        assert!(app.world().resource::<MapChanged>().0);
+        app.update();
+        assert!(!app.world().resource::<MapChanged>().0);
+    }
+}